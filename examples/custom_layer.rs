@@ -0,0 +1,76 @@
+use pge::{PGE, Pixel, GameLoop, LayerFrame, LayerRenderer};
+use pge::miniquad::*;
+
+// draws a single triangle straight on the GPU, no sprite involved
+struct TriangleRenderer {
+    pipeline: Pipeline,
+    bindings: Bindings,
+}
+
+impl TriangleRenderer {
+    fn new(ctx: &mut dyn RenderingBackend) -> Self {
+        let vertices: [f32; 6] = [-0.5, -0.5, 0.5, -0.5, 0.0, 0.5];
+        let vertex_buffer = ctx.new_buffer(BufferType::VertexBuffer, BufferUsage::Immutable, BufferSource::slice(&vertices));
+        let index_buffer = ctx.new_buffer(BufferType::IndexBuffer, BufferUsage::Immutable, BufferSource::slice(&[0u16, 1, 2]));
+
+        let shader = ctx.new_shader(
+            ShaderSource::Glsl { vertex: VERTEX, fragment: FRAGMENT },
+            ShaderMeta { images: vec![], uniforms: UniformBlockLayout { uniforms: vec![] } },
+        ).unwrap();
+
+        let pipeline = ctx.new_pipeline(
+            &[BufferLayout::default()],
+            &[VertexAttribute::new("in_pos", VertexFormat::Float2)],
+            shader,
+            PipelineParams::default(),
+        );
+
+        TriangleRenderer {
+            pipeline,
+            bindings: Bindings { vertex_buffers: vec![vertex_buffer], index_buffer, images: vec![] },
+        }
+    }
+}
+
+impl LayerRenderer for TriangleRenderer {
+    fn render(&mut self, ctx: &mut dyn RenderingBackend, _layer: &LayerFrame) {
+        ctx.apply_pipeline(&self.pipeline);
+        ctx.apply_bindings(&self.bindings);
+        ctx.draw(0, 3, 1);
+    }
+}
+
+const VERTEX: &str = r#"#version 100
+attribute vec2 in_pos;
+void main() {
+    gl_Position = vec4(in_pos, 0, 1);
+}"#;
+
+const FRAGMENT: &str = r#"#version 100
+void main() {
+    gl_FragColor = vec4(1.0, 0.5, 0.0, 1.0);
+}"#;
+
+struct GameState;
+
+impl GameLoop for GameState {
+    type GameType = GameState;
+
+    fn init(pge: &mut PGE) -> Self {
+        let renderer = TriangleRenderer::new(pge.rendering_backend().unwrap());
+        let layer = pge.create_layer();
+        pge.enable_layer(layer, true);
+        pge.set_layer_custom_render_function(layer, renderer);
+        GameState
+    }
+
+    fn update(&mut self, pge: &mut PGE, _dt: f64) {
+        pge.clear(&Pixel::rgb(0,0,100));
+        let (x, y) = (pge.get_mouse_x(), pge.get_mouse_y());
+        pge.fill_circle(x, y, 10, &Pixel::rgb(255,255,255));
+    }
+}
+
+fn main() {
+    PGE::construct::<GameState>("Custom Layer", 320, 240, 2, 2);
+}
@@ -1,32 +1,30 @@
-use pge::{PGE, Pixel, GameLoop, Sprite, Decal, SpriteRef, Layer, BLANK, WHITE, PixelMode, color};
+use pge::{PGE, GameLoop, Decal, SpriteRef, Flip, BLANK, WHITE, PixelMode};
+use pge::glam::{vec2, Vec2};
 
 struct GameState {
     logo_ref: SpriteRef,
     logo_decal: Decal,
+    layer: usize,
 }
 
 impl GameLoop for GameState {
     type GameType = GameState;
 
     fn init(pge: &mut PGE) -> Self {
-        let data = include_bytes!("../logo_long.png");
-        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png).unwrap();
-        let raw_image = image.as_bytes();
-        
-        let logo = Sprite::new_with_data(image.width(), image.height(), raw_image);
-        let (logo_decal, logo_ref) = Decal::new_from_sprite(pge, logo);
+        let (logo_decal, logo_ref) = Decal::from_bytes(pge, include_bytes!("../logo_long.png")).unwrap();
 
         // or 
 
-        //let logo_ref = SpriteRef::new_with_data(image.width(), image.height(), raw_image);
+        //let logo_ref = SpriteRef::load("logo_long.png").unwrap();
         //let logo_decal = Decal::new_from_sprite_ref(pge, &logo_ref);
 
-        let layer = Layer::new(pge, 640, 480);
-        pge.layers.push(layer);
+        let layer = pge.create_layer();
+        pge.enable_layer(layer, true);
 
         GameState {
             logo_ref,
-            logo_decal
+            logo_decal,
+            layer,
         }
     }
 
@@ -39,11 +37,12 @@ impl GameLoop for GameState {
         // we can still draw a SpriteRef with the CPU drawing methods and even edit it,
         // we just have to use this ugly syntax.
         pge.pixel_mode = PixelMode::Alpha;
-        pge.draw_sprite(x - 50, y, &self.logo_ref.get_sprite(), 1);
-        pge.current_layer = 1;
+        pge.draw_sprite(x - 50, y, &self.logo_ref.get_sprite(), 1, Flip::None);
+        pge.set_draw_target(self.layer, true);
         pge.clear(&BLANK);
-        pge.draw_sprite(x + 100, y - 100, &self.logo_ref.get_sprite(), 2);
-        pge.current_layer = 0;
+        pge.draw_sprite(x + 100, y - 100, &self.logo_ref.get_sprite(), 2, Flip::None);
+        pge.draw_decal(vec2(x as f32, y as f32 + 100.0), &self.logo_decal, Vec2::ONE, &WHITE);
+        pge.set_draw_target(0, true);
     }
 }
 
@@ -0,0 +1,33 @@
+use pge::{PGE, Pixel, GameLoop, App};
+
+struct GameState {
+    x: i32,
+}
+
+impl GameLoop for GameState {
+    type GameType = GameState;
+
+    fn init(_pge: &mut PGE) -> Self {
+        GameState { x: 0 }
+    }
+
+    fn update(&mut self, pge: &mut PGE, _dt: f64) {
+        pge.clear(&Pixel::rgb(0,0,100));
+        pge.fill_rect(self.x, 10, 10, 10, &Pixel::rgb(255,255,0));
+        self.x += 1;
+    }
+}
+
+fn main() {
+    // no window is opened, frames are composited on the CPU
+    let mut app = App::<GameState>::new(PGE::new_headless(64, 32));
+    app.step(10);
+
+    let frame = app.pge.get_frame().unwrap();
+    for y in 0..frame.height as i32 {
+        let row: String = (0..frame.width as i32)
+            .map(|x| if frame.get_pixel(x, y).r > 0 { '#' } else { '.' })
+            .collect();
+        println!("{}", row);
+    }
+}
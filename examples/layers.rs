@@ -0,0 +1,51 @@
+use pge::{PGE, Pixel, GameLoop, DecalMode, EngineConfig, PgeError, WHITE, BLANK};
+
+struct GameState {
+    clouds: usize,
+    glow: usize,
+}
+
+impl GameLoop for GameState {
+    type GameType = GameState;
+
+    fn init(pge: &mut PGE) -> Self {
+        // layers are drawn in the order they are created, the back buffer first
+        let clouds = pge.create_layer();
+        pge.enable_layer(clouds, true);
+        pge.set_layer_tint(clouds, &Pixel::rgba(255, 255, 255, 160));
+
+        let glow = pge.create_layer();
+        pge.enable_layer(glow, true);
+        pge.set_layer_blend(glow, DecalMode::Additive);
+
+        pge.set_draw_target(clouds, true);
+        pge.clear(&BLANK);
+        for i in 0..8 {
+            pge.fill_circle(i * 45, 60 + (i * 37) % 100, 20, &WHITE);
+        }
+
+        GameState { clouds, glow }
+    }
+
+    fn update(&mut self, pge: &mut PGE, _dt: f64) {
+        pge.set_draw_target(0, true);
+        pge.clear(&Pixel::rgb(0, 0, 100));
+
+        // scroll the clouds without redrawing them
+        let t = pge.frames as f32 * 0.002;
+        pge.set_layer_offset(self.clouds, t.fract() - 0.5, 0.0);
+
+        pge.set_draw_target(self.glow, true);
+        pge.clear(&BLANK);
+        let (x, y) = (pge.get_mouse_x(), pge.get_mouse_y());
+        pge.fill_circle(x, y, 30, &Pixel::rgb(128, 64, 0));
+        pge.set_draw_target(0, true);
+    }
+}
+
+fn main() -> Result<(), PgeError> {
+    let config = EngineConfig::new("Layers", 320, 240)
+        .pixel_size(2, 2)
+        .pixel_cohesion(false);
+    PGE::start::<GameState>(config)
+}
@@ -1,4 +1,4 @@
-use pge::{PGE, Pixel, GameLoop, Sprite};
+use pge::{PGE, Pixel, GameLoop, Sprite, Flip};
 
 struct GameState {
     logo: Sprite,
@@ -8,12 +8,8 @@ impl GameLoop for GameState {
     type GameType = GameState;
 
     fn init(_pge: &mut PGE) -> Self {
-        let data = include_bytes!("../logo_long.png");
-        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png).unwrap();
-        let raw_image = image.as_bytes();
-        
         GameState {
-            logo: Sprite::new_with_data(image.width(), image.height(), raw_image)
+            logo: Sprite::from_bytes(include_bytes!("../logo_long.png")).unwrap()
         }
     }
 
@@ -23,7 +19,8 @@ impl GameLoop for GameState {
         let x = pge.get_mouse_x();
         let y = pge.get_mouse_y();
 
-        pge.draw_sprite(x, y, &self.logo, 1);
+        pge.draw_sprite(x, y, &self.logo, 1, Flip::None);
+        pge.draw_sprite(x, y + self.logo.height as i32, &self.logo, 1, Flip::Vertical);
     }
 }
 
@@ -0,0 +1,406 @@
+use std::{collections::HashMap, hash::Hash, ops::Range};
+
+use glam::*;
+use serde_json::Value;
+
+use crate::*;
+
+/*
+    Frame animation over a SpriteSheet, after olcPGEX_Animate2D. The states are anything
+    hashable, usually an enum of the game's own.
+
+    let walk = Animation::new(PlayMode::Loop).frames(0..4, 0.1).event(2, "step");
+    let mut animator = Animator::new();
+    animator.add(State::Walk, walk);
+    animator.play(State::Walk);
+
+    // in update
+    animator.update(dt);
+    for event in animator.events() { ... }
+    animator.draw_decal(pge, &sheet, pos, Vec2::ONE, Flip::None, &WHITE);
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlayMode {
+    /// Starts over after the last frame
+    Loop,
+    /// Runs forwards then backwards, the end frames are shown once per turn
+    PingPong,
+    /// Stops on the last frame
+    Once,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnimationFrame {
+    /// Index of the frame in the sprite sheet
+    pub frame: usize,
+    /// Seconds
+    pub duration: f64,
+    /// Reported through `Animator::events` when the frame comes up
+    pub event: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimationEvent {
+    /// A frame with an event came up
+    Frame(String),
+    /// A looping animation went back to its first frame
+    Looped,
+    /// A `PlayMode::Once` animation reached its end
+    Finished,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlayMode,
+}
+
+impl Animation {
+    pub fn new(mode: PlayMode) -> Self {
+        Animation { frames: Vec::new(), mode }
+    }
+
+    /// Appends a sheet frame shown for `duration` seconds
+    pub fn frame(mut self, frame: usize, duration: f64) -> Self {
+        self.frames.push(AnimationFrame { frame, duration, event: None });
+        self
+    }
+
+    /// Appends a run of sheet frames that are all shown for `duration` seconds
+    pub fn frames(mut self, frames: Range<usize>, duration: f64) -> Self {
+        self.frames.extend(frames.map(|frame| AnimationFrame { frame, duration, event: None }));
+        self
+    }
+
+    /// Attaches an event to the frame at `position` in this animation, not in the sheet
+    pub fn event(mut self, position: usize, name: &str) -> Self {
+        if let Some(frame) = self.frames.get_mut(position) {
+            frame.event = Some(name.to_owned());
+        }
+        self
+    }
+
+    /// Length of one pass through the frames, in seconds
+    pub fn duration(&self) -> f64 {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+
+    /// Reads the `meta.frameTags` of an Aseprite JSON export into one animation per tag.
+    /// Frame numbers index `sheet`, which should come from the same export, and frame
+    /// durations come from the sheet. Frames without a duration get `default_duration`.
+    pub fn from_aseprite_json(sheet: &SpriteSheet, json: &str, default_duration: f64) -> Result<HashMap<String, Animation>, PgeError> {
+        let json: Value = serde_json::from_str(json).map_err(|e| PgeError::InvalidSpriteSheet(e.to_string()))?;
+        let tags = match &json["meta"]["frameTags"] {
+            Value::Array(tags) => tags.as_slice(),
+            Value::Null => &[],
+            _ => return Err(PgeError::InvalidSpriteSheet("frameTags is not a list".to_owned())),
+        };
+
+        let mut animations = HashMap::new();
+        for tag in tags {
+            let field = |key: &str| tag[key].as_u64().map(|n| n as usize)
+                .ok_or_else(|| PgeError::InvalidSpriteSheet(format!("frame tag without {}", key)));
+            let name = tag["name"].as_str().ok_or_else(|| PgeError::InvalidSpriteSheet("frame tag without name".to_owned()))?;
+            let (from, to) = (field("from")?, field("to")?);
+            let direction = AseDirection::from_name(tag["direction"].as_str().unwrap_or("forward"));
+            animations.insert(name.to_owned(), Animation::from_tag(sheet, from, to, direction, default_duration)?);
+        }
+        Ok(animations)
+    }
+
+    pub(crate) fn from_tag(sheet: &SpriteSheet, from: usize, to: usize, direction: AseDirection, default_duration: f64) -> Result<Animation, PgeError> {
+        if from > to || to >= sheet.len() {
+            return Err(PgeError::InvalidSpriteSheet(format!("frame tag {}..={} is outside the sheet", from, to)));
+        }
+        let mode = match direction {
+            AseDirection::PingPong | AseDirection::PingPongReverse => PlayMode::PingPong,
+            AseDirection::Forward | AseDirection::Reverse => PlayMode::Loop,
+        };
+        let mut animation = Animation::new(mode);
+        for frame in from..=to {
+            animation = animation.frame(frame, sheet.frames()[frame].duration.unwrap_or(default_duration));
+        }
+        if matches!(direction, AseDirection::Reverse | AseDirection::PingPongReverse) {
+            animation.frames.reverse();
+        }
+        Ok(animation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Animator<S> {
+    animations: HashMap<S, Animation>,
+    state: Option<S>,
+    // position in the current animation's frames
+    position: usize,
+    // time spent on the current frame
+    time: f64,
+    // false while a ping pong animation runs backwards
+    forwards: bool,
+    finished: bool,
+    events: Vec<AnimationEvent>,
+}
+
+impl<S: Eq + Hash + Clone> Default for Animator<S> {
+    fn default() -> Self {
+        Animator::new()
+    }
+}
+
+impl<S: Eq + Hash + Clone> Animator<S> {
+    pub fn new() -> Self {
+        Animator {
+            animations: HashMap::new(),
+            state: None,
+            position: 0,
+            time: 0.0,
+            forwards: true,
+            finished: false,
+            events: Vec::new(),
+        }
+    }
+
+    /// Adds or replaces the animation for a state. The first one added starts playing.
+    pub fn add(&mut self, state: S, animation: Animation) {
+        self.animations.insert(state.clone(), animation);
+        match &self.state {
+            None => self.play(state),
+            // the old frames are gone
+            Some(current) if *current == state => self.restart(),
+            Some(_) => {}
+        }
+    }
+
+    /// Switches to another state's animation from its first frame. Playing the current state
+    /// again carries on where it is, use `restart` to start it over.
+    pub fn play(&mut self, state: S) {
+        if self.state.as_ref() != Some(&state) {
+            self.state = Some(state);
+            self.restart();
+        }
+    }
+
+    pub fn restart(&mut self) {
+        self.position = 0;
+        self.time = 0.0;
+        self.forwards = true;
+        self.finished = false;
+        self.frame_event();
+    }
+
+    pub fn state(&self) -> Option<&S> {
+        self.state.as_ref()
+    }
+
+    pub fn animation(&self) -> Option<&Animation> {
+        self.animations.get(self.state.as_ref()?)
+    }
+
+    /// Whether a `PlayMode::Once` animation has reached its end
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// What happened during the last `update` and in `play` or `restart` calls after it
+    pub fn events(&self) -> &[AnimationEvent] {
+        &self.events
+    }
+
+    /// Index of the sheet frame to draw
+    pub fn current_frame(&self) -> Option<usize> {
+        self.animation()?.frames.get(self.position).map(|f| f.frame)
+    }
+
+    /// Advances by `dt` seconds, usually the one `GameLoop::update` gets
+    pub fn update(&mut self, dt: f64) {
+        self.events.clear();
+        let Some(animation) = self.animation() else {
+            return;
+        };
+        // an animation with no length would never get through its frames
+        if self.finished || animation.duration() <= 0.0 {
+            return;
+        }
+
+        self.time += dt;
+        loop {
+            let duration = match self.animation() {
+                Some(animation) => animation.frames[self.position].duration,
+                None => return,
+            };
+            if self.time < duration || self.finished {
+                break;
+            }
+            self.time -= duration;
+            self.advance();
+        }
+    }
+
+    fn advance(&mut self) {
+        let Some(animation) = self.animation() else {
+            return;
+        };
+        let (count, mode) = (animation.frames.len(), animation.mode);
+        match mode {
+            PlayMode::Loop => {
+                self.position = (self.position + 1) % count;
+                if self.position == 0 {
+                    self.events.push(AnimationEvent::Looped);
+                }
+            }
+            PlayMode::Once => {
+                if self.position + 1 < count {
+                    self.position += 1;
+                } else {
+                    self.finished = true;
+                    self.time = 0.0;
+                    self.events.push(AnimationEvent::Finished);
+                    return;
+                }
+            }
+            PlayMode::PingPong => {
+                if count == 1 {
+                    self.events.push(AnimationEvent::Looped);
+                } else if self.forwards {
+                    self.position += 1;
+                    self.forwards = self.position + 1 < count;
+                } else {
+                    self.position -= 1;
+                    if self.position == 0 {
+                        self.forwards = true;
+                        self.events.push(AnimationEvent::Looped);
+                    }
+                }
+            }
+        }
+        self.frame_event();
+    }
+
+    fn frame_event(&mut self) {
+        let event = self.animation().and_then(|a| a.frames.get(self.position)).and_then(|f| f.event.clone());
+        if let Some(event) = event {
+            self.events.push(AnimationEvent::Frame(event));
+        }
+    }
+
+    /// Draws the current frame with `SpriteSheet::draw_frame`
+    pub fn draw(&self, pge: &mut PGE, sheet: &SpriteSheet, pos: IVec2, scale: usize, flip: Flip) {
+        if let Some(frame) = self.current_frame() {
+            sheet.draw_frame(pge, frame, pos, scale, flip);
+        }
+    }
+
+    /// Draws the current frame with `SpriteSheet::draw_frame_decal`
+    pub fn draw_decal(&self, pge: &mut PGE, sheet: &SpriteSheet, pos: Vec2, scale: Vec2, flip: Flip, tint: &Color) {
+        if let Some(frame) = self.current_frame() {
+            sheet.draw_frame_decal(pge, frame, pos, scale, flip, tint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // quarter seconds add up exactly
+    const DT: f64 = 0.25;
+
+    fn animator(animation: Animation) -> Animator<u8> {
+        let mut animator = Animator::new();
+        animator.add(0, animation);
+        animator
+    }
+
+    // the frame shown after each of `steps` updates of `dt`
+    fn sequence(animator: &mut Animator<u8>, steps: usize, dt: f64) -> Vec<usize> {
+        (0..steps).map(|_| {
+            animator.update(dt);
+            animator.current_frame().unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn loop_starts_over() {
+        let mut animator = animator(Animation::new(PlayMode::Loop).frames(0..3, DT));
+        assert_eq!(animator.current_frame(), Some(0));
+        assert_eq!(sequence(&mut animator, 5, DT), [1, 2, 0, 1, 2]);
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut animator = animator(Animation::new(PlayMode::Once).frames(4..7, DT));
+        assert_eq!(sequence(&mut animator, 2, DT), [5, 6]);
+        assert!(!animator.is_finished());
+
+        animator.update(DT);
+        assert_eq!(animator.events(), [AnimationEvent::Finished]);
+        assert!(animator.is_finished());
+        assert_eq!(sequence(&mut animator, 3, DT), [6, 6, 6]);
+        assert!(animator.events().is_empty());
+
+        animator.restart();
+        assert_eq!(animator.current_frame(), Some(4));
+    }
+
+    #[test]
+    fn ping_pong_turns_at_both_ends() {
+        let mut four = animator(Animation::new(PlayMode::PingPong).frames(0..4, DT));
+        assert_eq!(sequence(&mut four, 8, DT), [1, 2, 3, 2, 1, 0, 1, 2]);
+
+        // a single frame has nowhere to turn
+        let mut single = animator(Animation::new(PlayMode::PingPong).frame(9, DT));
+        assert_eq!(sequence(&mut single, 2, DT), [9, 9]);
+        assert_eq!(single.events(), [AnimationEvent::Looped]);
+    }
+
+    #[test]
+    fn events_fire_as_frames_come_up() {
+        let mut animator = animator(Animation::new(PlayMode::Loop).frames(0..3, DT).event(0, "start").event(2, "step"));
+        // the first frame comes up when the animation starts playing
+        assert_eq!(animator.events(), [AnimationEvent::Frame("start".to_owned())]);
+
+        let mut events = Vec::new();
+        for _ in 0..3 {
+            animator.update(DT);
+            events.push(animator.events().to_vec());
+        }
+        assert_eq!(events, [
+            vec![],
+            vec![AnimationEvent::Frame("step".to_owned())],
+            vec![AnimationEvent::Looped, AnimationEvent::Frame("start".to_owned())],
+        ]);
+
+        // partway through a frame nothing happens
+        animator.update(DT / 2.0);
+        assert!(animator.events().is_empty());
+    }
+
+    #[test]
+    fn dt_longer_than_a_cycle() {
+        // 8 frames' worth in one update
+        let mut looped = animator(Animation::new(PlayMode::Loop).frames(0..3, DT).event(1, "step"));
+        looped.update(8.0 * DT);
+        assert_eq!(looped.current_frame(), Some(2));
+        let step = AnimationEvent::Frame("step".to_owned());
+        assert_eq!(looped.events(), [step.clone(), AnimationEvent::Looped, step.clone(), AnimationEvent::Looped, step]);
+
+        let mut ping_pong = animator(Animation::new(PlayMode::PingPong).frames(0..4, DT));
+        ping_pong.update(8.0 * DT);
+        assert_eq!(ping_pong.current_frame(), Some(2));
+        assert_eq!(ping_pong.events(), [AnimationEvent::Looped]);
+
+        let mut once = animator(Animation::new(PlayMode::Once).frames(0..3, DT));
+        once.update(8.0 * DT);
+        assert_eq!(once.current_frame(), Some(2));
+        assert_eq!(once.events(), [AnimationEvent::Finished]);
+    }
+
+    #[test]
+    fn zero_length_animation_stays_put() {
+        let mut animator = animator(Animation::new(PlayMode::Loop).frames(0..3, 0.0));
+        animator.update(1.0);
+        assert_eq!(animator.current_frame(), Some(0));
+    }
+}
@@ -0,0 +1,479 @@
+use std::{collections::HashMap, io::Read, path::Path};
+
+use flate2::read::ZlibDecoder;
+use glam::*;
+
+use crate::{reader::Reader, *};
+
+/*
+    Reads .ase/.aseprite files, following
+    https://github.com/aseprite/aseprite/blob/main/docs/ase-file-specs.md
+
+    let ase = AsepriteFile::load("hero.aseprite")?;
+    let mut sheet = ase.sprite_sheet()?;
+    let animations = ase.animations(&sheet, 0.1)?;
+
+    Every colour depth ends up as rgba. Layers are composited with normal blending whatever
+    their blend mode says, hidden layers and layers in hidden groups are left out. Tilemap
+    layers are skipped.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AseLayerKind {
+    Normal,
+    Group,
+    Tilemap,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseLayer {
+    pub name: String,
+    pub kind: AseLayerKind,
+    pub visible: bool,
+    /// The opaque bottom layer, its transparent colour index is a plain colour
+    pub background: bool,
+    pub opacity: u8,
+    /// Aseprite's blend mode number, 0 is normal
+    pub blend_mode: u16,
+    /// How deep the layer is nested in groups, 0 at the top
+    pub child_level: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseCel {
+    pub layer: usize,
+    /// Where the image sits on the canvas
+    pub pos: IVec2,
+    pub opacity: u8,
+    pub image: Sprite,
+}
+
+#[derive(Debug, Clone)]
+pub struct AseFrame {
+    /// Seconds
+    pub duration: f64,
+    pub cels: Vec<AseCel>,
+}
+
+/// The order a tag's frames play in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AseDirection {
+    Forward,
+    Reverse,
+    PingPong,
+    /// Ping pong starting from the last frame
+    PingPongReverse,
+}
+
+impl AseDirection {
+    // the names the JSON export uses, unknown ones play forward
+    pub(crate) fn from_name(name: &str) -> AseDirection {
+        match name {
+            "reverse" => AseDirection::Reverse,
+            "pingpong" => AseDirection::PingPong,
+            "pingpong_reverse" => AseDirection::PingPongReverse,
+            _ => AseDirection::Forward,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AseTag {
+    pub name: String,
+    pub from: usize,
+    pub to: usize,
+    pub direction: AseDirection,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsepriteFile {
+    pub width: u32,
+    pub height: u32,
+    pub layers: Vec<AseLayer>,
+    pub frames: Vec<AseFrame>,
+    pub tags: Vec<AseTag>,
+    /// Empty unless the file has a palette chunk
+    pub palette: Vec<Pixel>,
+}
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_TAGS: u16 = 0x2018;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+// header flag saying layer opacity is meaningful
+const FLAG_LAYER_OPACITY: u32 = 1;
+
+// most pixels a canvas, cel or sheet may have, a GiB of rgba. Sizes come from the
+// file, so they are checked before anything that big gets allocated.
+const MAX_PIXELS: usize = 1 << 28;
+
+// cel pixels stay in the file's colour depth until the palette is known
+struct RawCel {
+    frame: usize,
+    layer: usize,
+    pos: IVec2,
+    opacity: u8,
+    content: CelContent,
+}
+
+enum CelContent {
+    Pixels { width: u32, height: u32, data: Vec<u8> },
+    // the cel of the same layer in another frame
+    Linked(usize),
+}
+
+impl AsepriteFile {
+    /// Reads a file through `read_resource`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PgeError> {
+        AsepriteFile::from_bytes(&read_resource(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PgeError> {
+        let mut r = Reader::new(bytes, truncated);
+        r.u32()?; // file size
+        if r.u16()? != HEADER_MAGIC {
+            return Err(invalid("not an aseprite file"));
+        }
+        let frame_count = r.u16()? as usize;
+        let width = r.u16()? as u32;
+        let height = r.u16()? as u32;
+        let depth = r.u16()?;
+        let flags = r.u32()?;
+        r.skip(2 + 4 + 4)?; // speed and two zeros
+        let transparent_index = r.u8()?;
+        r.seek(128)?;
+        pixel_count(width, height)?;
+
+        let mut ase = AsepriteFile { width, height, layers: Vec::new(), frames: Vec::new(), tags: Vec::new(), palette: Vec::new() };
+        let mut cels = Vec::new();
+        for frame in 0..frame_count {
+            let start = r.pos;
+            let frame_size = r.u32()? as usize;
+            if r.u16()? != FRAME_MAGIC {
+                return Err(invalid("bad frame header"));
+            }
+            let old_chunks = r.u16()? as usize;
+            let duration = r.u16()? as f64 / 1000.0;
+            r.skip(2)?;
+            let chunks = match r.u32()? as usize {
+                0 => old_chunks,
+                n => n,
+            };
+            ase.frames.push(AseFrame { duration, cels: Vec::new() });
+
+            for _ in 0..chunks {
+                let chunk_start = r.pos;
+                let chunk_size = r.u32()? as usize;
+                let chunk_type = r.u16()?;
+                let end = chunk_start.checked_add(chunk_size).filter(|&end| end <= bytes.len() && chunk_size >= 6)
+                    .ok_or_else(|| invalid("truncated chunk"))?;
+                let mut chunk = Reader::new(&bytes[..end], truncated);
+                chunk.seek(r.pos)?;
+                match chunk_type {
+                    CHUNK_LAYER => ase.layers.push(read_layer(&mut chunk, flags)?),
+                    CHUNK_CEL => cels.push(read_cel(&mut chunk, frame, depth)?),
+                    CHUNK_TAGS => ase.tags = read_tags(&mut chunk)?,
+                    CHUNK_PALETTE => read_palette(&mut chunk, &mut ase.palette)?,
+                    // only used when there is no new palette chunk
+                    CHUNK_OLD_PALETTE if ase.palette.is_empty() => read_old_palette(&mut chunk, &mut ase.palette)?,
+                    _ => {}
+                }
+                r.seek(end)?;
+            }
+            r.seek(start + frame_size)?;
+        }
+
+        for cel in &cels {
+            let image = match &cel.content {
+                CelContent::Pixels { width, height, data } => {
+                    let background = ase.layers.get(cel.layer).is_some_and(|l| l.background);
+                    to_sprite(*width, *height, data, depth, &ase.palette, transparent_index, background)?
+                }
+                CelContent::Linked(frame) => {
+                    let linked = ase.frames.get(*frame).and_then(|f| f.cels.iter().find(|c| c.layer == cel.layer));
+                    match linked {
+                        Some(linked) => linked.image.clone(),
+                        None => continue,
+                    }
+                }
+            };
+            ase.frames[cel.frame].cels.push(AseCel { layer: cel.layer, pos: cel.pos, opacity: cel.opacity, image });
+        }
+        Ok(ase)
+    }
+
+    // hidden layers hide everything nested in them
+    fn layer_visible(&self, layer: usize) -> bool {
+        let mut level = self.layers[layer].child_level;
+        if !self.layers[layer].visible {
+            return false;
+        }
+        for parent in self.layers[..layer].iter().rev() {
+            if level == 0 {
+                break;
+            }
+            if parent.child_level < level {
+                if !parent.visible {
+                    return false;
+                }
+                level = parent.child_level;
+            }
+        }
+        true
+    }
+
+    /// All visible layers of a frame composited into one canvas sized sprite
+    pub fn frame_image(&self, frame: usize) -> Sprite {
+        let layers: Vec<usize> = (0..self.layers.len()).filter(|&l| self.layer_visible(l)).collect();
+        self.composite(frame, &layers)
+    }
+
+    /// Just one layer of a frame, whether it is visible or not
+    pub fn layer_image(&self, frame: usize, layer: usize) -> Sprite {
+        self.composite(frame, &[layer])
+    }
+
+    fn composite(&self, frame: usize, layers: &[usize]) -> Sprite {
+        let mut canvas = Sprite::new(self.width, self.height);
+        let Some(frame) = self.frames.get(frame) else {
+            return canvas;
+        };
+        for &layer in layers {
+            let Some(info) = self.layers.get(layer) else {
+                continue;
+            };
+            if info.kind != AseLayerKind::Normal {
+                continue;
+            }
+            for cel in frame.cels.iter().filter(|c| c.layer == layer) {
+                let opacity = cel.opacity as u32 * info.opacity as u32 / 255;
+                for y in 0..cel.image.height as i32 {
+                    for x in 0..cel.image.width as i32 {
+                        let (cx, cy) = (cel.pos.x + x, cel.pos.y + y);
+                        let mut src = cel.image.get_pixel(x, y);
+                        src.a = (src.a as u32 * opacity / 255) as u8;
+                        let dst = canvas.get_pixel(cx, cy);
+                        canvas.set_pixel(cx, cy, &over(src, dst));
+                    }
+                }
+            }
+        }
+        canvas
+    }
+
+    /// Every frame flattened into a grid, frame i is sheet frame i with the frame's duration.
+    /// Fails when the grid would be too large for one sprite.
+    pub fn sprite_sheet(&self) -> Result<SpriteSheet, PgeError> {
+        self.sheet(|frame| self.frame_image(frame))
+    }
+
+    /// Like `sprite_sheet` with only one layer, for layers that are drawn separately
+    pub fn layer_sprite_sheet(&self, layer: usize) -> Result<SpriteSheet, PgeError> {
+        self.sheet(|frame| self.layer_image(frame, layer))
+    }
+
+    fn sheet(&self, image: impl Fn(usize) -> Sprite) -> Result<SpriteSheet, PgeError> {
+        let count = self.frames.len() as u32;
+        let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+        let rows = count.div_ceil(columns);
+        let too_large = || invalid("too many frames for one sheet");
+        let width = columns.checked_mul(self.width).ok_or_else(too_large)?;
+        let height = rows.checked_mul(self.height).ok_or_else(too_large)?;
+        pixel_count(width, height).map_err(|_| too_large())?;
+        let mut sprite = Sprite::new(width, height);
+        let mut cells = Vec::new();
+        for frame in 0..count {
+            let pos = uvec2(frame % columns * self.width, frame / columns * self.height);
+            let image = image(frame as usize);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    sprite.set_pixel((pos.x + x) as i32, (pos.y + y) as i32, &image.get_pixel(x as i32, y as i32));
+                }
+            }
+            cells.push(pos.as_ivec2());
+        }
+
+        let mut sheet = SpriteSheet::new(sprite);
+        let size = ivec2(self.width as i32, self.height as i32);
+        for (i, pos) in cells.into_iter().enumerate() {
+            sheet.add_frame(&i.to_string(), pos, size);
+            // aseprite never writes zero, but a zero would stall an animation
+            sheet.frames[i].duration = Some(self.frames[i].duration).filter(|&d| d > 0.0);
+        }
+        Ok(sheet)
+    }
+
+    /// One animation per tag over a sheet from `sprite_sheet` or `layer_sprite_sheet`.
+    /// Frames without a duration in the sheet get `default_duration` seconds.
+    pub fn animations(&self, sheet: &SpriteSheet, default_duration: f64) -> Result<HashMap<String, Animation>, PgeError> {
+        let mut animations = HashMap::new();
+        for tag in &self.tags {
+            animations.insert(tag.name.clone(), Animation::from_tag(sheet, tag.from, tag.to, tag.direction, default_duration)?);
+        }
+        Ok(animations)
+    }
+}
+
+fn read_layer(r: &mut Reader, header_flags: u32) -> Result<AseLayer, PgeError> {
+    let flags = r.u16()?;
+    let kind = match r.u16()? {
+        0 => AseLayerKind::Normal,
+        1 => AseLayerKind::Group,
+        _ => AseLayerKind::Tilemap,
+    };
+    let child_level = r.u16()?;
+    r.skip(4)?; // default size, unused
+    let blend_mode = r.u16()?;
+    let opacity = r.u8()?;
+    r.skip(3)?;
+    let name = r.string()?;
+    Ok(AseLayer {
+        name,
+        kind,
+        visible: flags & 1 != 0,
+        background: flags & 8 != 0,
+        opacity: if header_flags & FLAG_LAYER_OPACITY != 0 { opacity } else { 255 },
+        blend_mode,
+        child_level,
+    })
+}
+
+fn read_cel(r: &mut Reader, frame: usize, depth: u16) -> Result<RawCel, PgeError> {
+    let layer = r.u16()? as usize;
+    let pos = ivec2(r.i16()? as i32, r.i16()? as i32);
+    let opacity = r.u8()?;
+    let cel_type = r.u16()?;
+    r.skip(2 + 5)?; // z-index and reserved
+    let content = match cel_type {
+        0 => {
+            let (width, height) = (r.u16()? as u32, r.u16()? as u32);
+            let data = r.take(cel_len(width, height, depth)?)?.to_vec();
+            CelContent::Pixels { width, height, data }
+        }
+        1 => CelContent::Linked(r.u16()? as usize),
+        2 => {
+            let (width, height) = (r.u16()? as u32, r.u16()? as u32);
+            // a small stream can inflate to anything, stop at what the cel needs
+            let len = cel_len(width, height, depth)?;
+            let mut data = Vec::new();
+            ZlibDecoder::new(r.rest()).take(len as u64).read_to_end(&mut data).map_err(|_| invalid("bad compressed cel"))?;
+            CelContent::Pixels { width, height, data }
+        }
+        // tilemaps, not supported
+        _ => CelContent::Pixels { width: 0, height: 0, data: Vec::new() },
+    };
+    Ok(RawCel { frame, layer, pos, opacity, content })
+}
+
+fn read_tags(r: &mut Reader) -> Result<Vec<AseTag>, PgeError> {
+    let count = r.u16()?;
+    r.skip(8)?;
+    let mut tags = Vec::new();
+    for _ in 0..count {
+        let from = r.u16()? as usize;
+        let to = r.u16()? as usize;
+        let direction = match r.u8()? {
+            1 => AseDirection::Reverse,
+            2 => AseDirection::PingPong,
+            3 => AseDirection::PingPongReverse,
+            _ => AseDirection::Forward,
+        };
+        r.skip(2 + 6 + 3 + 1)?; // repeat, reserved, old colour, extra
+        let name = r.string()?;
+        tags.push(AseTag { name, from, to, direction });
+    }
+    Ok(tags)
+}
+
+fn read_palette(r: &mut Reader, palette: &mut Vec<Pixel>) -> Result<(), PgeError> {
+    let size = r.u32()? as usize;
+    let first = r.u32()? as usize;
+    let last = r.u32()? as usize;
+    r.skip(8)?;
+    if size > u16::MAX as usize || first > last || last >= size {
+        return Err(invalid("bad palette"));
+    }
+    palette.resize(size, BLANK);
+    for entry in &mut palette[first..=last] {
+        let flags = r.u16()?;
+        let c = r.take(4)?;
+        *entry = Pixel::rgba(c[0], c[1], c[2], c[3]);
+        if flags & 1 != 0 {
+            r.string()?;
+        }
+    }
+    Ok(())
+}
+
+fn read_old_palette(r: &mut Reader, palette: &mut Vec<Pixel>) -> Result<(), PgeError> {
+    let packets = r.u16()?;
+    let mut index = 0;
+    for _ in 0..packets {
+        index += r.u8()? as usize;
+        let count = match r.u8()? {
+            0 => 256,
+            n => n as usize,
+        };
+        for _ in 0..count {
+            let c = r.take(3)?;
+            if palette.len() <= index {
+                palette.resize(index + 1, BLANK);
+            }
+            palette[index] = Pixel::rgb(c[0], c[1], c[2]);
+            index += 1;
+        }
+    }
+    Ok(())
+}
+
+fn to_sprite(width: u32, height: u32, data: &[u8], depth: u16, palette: &[Pixel], transparent_index: u8, background: bool) -> Result<Sprite, PgeError> {
+    let bytes_per_pixel = (depth / 8) as usize;
+    if bytes_per_pixel == 0 || data.len() < cel_len(width, height, depth)? {
+        return Err(invalid("cel is smaller than its size"));
+    }
+    let mut sprite = Sprite::new(width, height);
+    for (p, c) in sprite.pixel_data.iter_mut().zip(data.chunks_exact(bytes_per_pixel)) {
+        *p = match depth {
+            32 => Pixel::rgba(c[0], c[1], c[2], c[3]),
+            16 => Pixel::rgba(c[0], c[0], c[0], c[1]),
+            8 if c[0] == transparent_index && !background => BLANK,
+            8 => palette.get(c[0] as usize).copied().unwrap_or(BLANK),
+            _ => return Err(invalid("unknown colour depth")),
+        };
+    }
+    Ok(sprite)
+}
+
+fn pixel_count(width: u32, height: u32) -> Result<usize, PgeError> {
+    (width as usize).checked_mul(height as usize).filter(|&n| n <= MAX_PIXELS).ok_or_else(|| invalid("image too large"))
+}
+
+// bytes of pixel data in a cel of the file's colour depth
+fn cel_len(width: u32, height: u32, depth: u16) -> Result<usize, PgeError> {
+    pixel_count(width, height)?.checked_mul((depth / 8) as usize).ok_or_else(|| invalid("image too large"))
+}
+
+// straight alpha source over destination
+fn over(src: Pixel, dst: Pixel) -> Pixel {
+    let sa = src.a as f32 / 255.0;
+    let da = dst.a as f32 / 255.0 * (1.0 - sa);
+    let a = sa + da;
+    if a <= 0.0 {
+        return BLANK;
+    }
+    let mix = |s: u8, d: u8| ((s as f32 * sa + d as f32 * da) / a).round() as u8;
+    Pixel::rgba(mix(src.r, dst.r), mix(src.g, dst.g), mix(src.b, dst.b), (a * 255.0).round() as u8)
+}
+
+fn invalid(reason: &str) -> PgeError {
+    PgeError::InvalidAseprite(reason.to_owned())
+}
+
+fn truncated() -> PgeError {
+    invalid("truncated file")
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, ops::Deref, rc::Weak};
+
+use glam::*;
+
+use crate::*;
+
+/*
+    Packs many sprites into a few large textures, so decals drawn from them share a texture
+    and the layer draws them in one batch.
+
+    let mut builder = AtlasBuilder::new(1024, 1024).padding(1).extrude(1);
+    let hero = builder.add("hero", Sprite::load("hero.png")?);
+    builder.add("coin", Sprite::load("coin.png")?);
+    let atlas = builder.build(pge)?;
+    pge.draw_decal(pos, atlas.region(hero).unwrap(), Vec2::ONE, &WHITE);
+    pge.draw_decal(pos, atlas.region("coin").unwrap(), Vec2::ONE, &WHITE);
+
+    Sprites go on shelves, tallest first. Extrusion repeats each sprite's edge pixels around it
+    so linear filtering never reaches into a neighbour, padding leaves blank pixels between them.
+*/
+
+#[derive(Debug)]
+pub struct AtlasBuilder {
+    page_size: UVec2,
+    padding: u32,
+    extrude: u32,
+    sprites: Vec<(String, Sprite)>,
+}
+
+/// A packed sprite. It derefs to a `Decal` covering just its part of the page, so it works
+/// with every decal drawing function.
+#[derive(Debug)]
+pub struct DecalRegion {
+    pub name: String,
+    /// Index of the page texture in `Atlas::pages`
+    pub page: usize,
+    decal: Decal,
+}
+
+impl Deref for DecalRegion {
+    type Target = Decal;
+
+    fn deref(&self) -> &Decal {
+        &self.decal
+    }
+}
+
+impl Clone for DecalRegion {
+    fn clone(&self) -> Self {
+        DecalRegion {
+            name: self.name.clone(),
+            page: self.page,
+            decal: Decal { sprite: Weak::new(), ..self.decal },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Atlas {
+    /// One decal per texture, covering all of it
+    pub pages: Vec<Decal>,
+    regions: Vec<DecalRegion>,
+    names: HashMap<String, usize>,
+}
+
+impl Atlas {
+    /// A region by the index `AtlasBuilder::add` returned or by its name
+    pub fn region<'a>(&self, id: impl Into<FrameId<'a>>) -> Option<&DecalRegion> {
+        match id.into() {
+            FrameId::Index(index) => self.regions.get(index),
+            FrameId::Name(name) => self.names.get(name).map(|&index| &self.regions[index]),
+        }
+    }
+
+    /// All regions in the order they were added
+    pub fn regions(&self) -> &[DecalRegion] {
+        &self.regions
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+}
+
+// a row of sprites across a page
+struct Shelf {
+    y: u32,
+    height: u32,
+    // where the next sprite goes
+    x: u32,
+}
+
+#[derive(Default)]
+struct Page {
+    shelves: Vec<Shelf>,
+    // below the last shelf, including its padding
+    bottom: u32,
+    // used area, pages are cropped to it
+    extent: UVec2,
+}
+
+impl AtlasBuilder {
+    /// Pages are at most `width` by `height`, the last one shrinks to what it holds
+    pub fn new(width: u32, height: u32) -> Self {
+        AtlasBuilder {
+            page_size: uvec2(width, height),
+            padding: 0,
+            extrude: 0,
+            sprites: Vec::new(),
+        }
+    }
+
+    /// Blank pixels between sprites
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Edge pixels repeated around every sprite
+    pub fn extrude(mut self, extrude: u32) -> Self {
+        self.extrude = extrude;
+        self
+    }
+
+    /// Queues a sprite and returns the index of its region
+    pub fn add(&mut self, name: &str, sprite: Sprite) -> usize {
+        self.sprites.push((name.to_owned(), sprite));
+        self.sprites.len() - 1
+    }
+
+    /// Packs the sprites and uploads one texture per page
+    pub fn build(self, pge: &mut PGE) -> Result<Atlas, PgeError> {
+        let border = uvec2(self.extrude, self.extrude) * 2;
+
+        // tallest first keeps the shelves full
+        let mut order: Vec<usize> = (0..self.sprites.len()).collect();
+        order.sort_by_key(|&i| {
+            let sprite = &self.sprites[i].1;
+            (std::cmp::Reverse(sprite.height), std::cmp::Reverse(sprite.width))
+        });
+
+        let mut pages: Vec<Page> = Vec::new();
+        // page and top left corner of the extruded cell of every sprite
+        let mut placements = vec![(0, UVec2::ZERO); self.sprites.len()];
+        for i in order {
+            let sprite = &self.sprites[i].1;
+            let cell = uvec2(sprite.width, sprite.height) + border;
+            if cell.x > self.page_size.x || cell.y > self.page_size.y {
+                return Err(PgeError::SpriteTooLarge { width: sprite.width as usize, height: sprite.height as usize });
+            }
+
+            let placed = pages.iter_mut().enumerate().find_map(|(p, page)| self.place(page, cell).map(|pos| (p, pos)));
+            placements[i] = match placed {
+                Some(placement) => placement,
+                None => {
+                    let mut page = Page::default();
+                    let pos = self.place(&mut page, cell).expect("a cell that fits the page fits an empty one");
+                    pages.push(page);
+                    (pages.len() - 1, pos)
+                }
+            };
+        }
+
+        let mut sheets: Vec<Sprite> = pages.iter().map(|page| Sprite::new(page.extent.x, page.extent.y)).collect();
+        for ((_, sprite), &(page, pos)) in self.sprites.iter().zip(&placements) {
+            self.copy_extruded(&mut sheets[page], sprite, pos);
+        }
+
+        let pages: Vec<Decal> = sheets.iter().map(|sheet| {
+            let id = pge.create_texture(sheet.width, sheet.height);
+            pge.update_texture(id, sheet);
+            Decal {
+                sprite: Weak::new(),
+                texture_id: id,
+                uv_offset: Vec2::ZERO,
+                uv_scale: Vec2::ONE,
+                width: sheet.width,
+                height: sheet.height,
+            }
+        }).collect();
+
+        let mut names = HashMap::new();
+        let regions = self.sprites.into_iter().zip(placements).enumerate().map(|(i, ((name, sprite), (page, pos)))| {
+            names.insert(name.clone(), i);
+            let page_size = pages[page].size();
+            let origin = (pos + uvec2(self.extrude, self.extrude)).as_vec2();
+            let size = vec2(sprite.width as f32, sprite.height as f32);
+            DecalRegion {
+                name,
+                page,
+                decal: Decal {
+                    sprite: Weak::new(),
+                    texture_id: pages[page].texture_id,
+                    uv_offset: origin / page_size,
+                    uv_scale: size / page_size,
+                    width: sprite.width,
+                    height: sprite.height,
+                },
+            }
+        }).collect();
+
+        Ok(Atlas { pages, regions, names })
+    }
+
+    // finds room for a cell on an existing shelf or opens a new one below them
+    fn place(&self, page: &mut Page, cell: UVec2) -> Option<UVec2> {
+        let shelf = match page.shelves.iter_mut().find(|s| s.height >= cell.y && s.x + cell.x <= self.page_size.x) {
+            Some(shelf) => shelf,
+            None => {
+                if page.bottom + cell.y > self.page_size.y {
+                    return None;
+                }
+                page.shelves.push(Shelf { y: page.bottom, height: cell.y, x: 0 });
+                page.bottom += cell.y + self.padding;
+                page.shelves.last_mut().unwrap()
+            }
+        };
+        let pos = uvec2(shelf.x, shelf.y);
+        shelf.x += cell.x + self.padding;
+        page.extent = page.extent.max(pos + cell);
+        Some(pos)
+    }
+
+    // draws the sprite at `pos` plus the extrusion, edge pixels repeat outwards
+    fn copy_extruded(&self, sheet: &mut Sprite, sprite: &Sprite, pos: UVec2) {
+        if sprite.width == 0 || sprite.height == 0 {
+            return;
+        }
+        let e = self.extrude as i32;
+        let (w, h) = (sprite.width as i32, sprite.height as i32);
+        for y in -e..h + e {
+            for x in -e..w + e {
+                let p = sprite.pixel_data[(y.clamp(0, h - 1) * w + x.clamp(0, w - 1)) as usize];
+                sheet.set_pixel(pos.x as i32 + e + x, pos.y as i32 + e + y, &p);
+            }
+        }
+    }
+}
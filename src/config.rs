@@ -0,0 +1,188 @@
+use miniquad::{conf::{self, Conf}, FilterMode};
+
+use crate::*;
+
+/*
+    Everything the engine needs to know before the window opens.
+
+    let config = EngineConfig::new("Game", 320, 240)
+        .pixel_size(2, 2)
+        .vsync(false)
+        .fixed_update_rate(50.0);
+    PGE::start::<GameState>(config)?;
+*/
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub title: String,
+    /// Size of the back buffer in screen pixels
+    pub width: usize,
+    pub height: usize,
+    /// Size of one screen pixel in window pixels
+    pub pixel_width: usize,
+    pub pixel_height: usize,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub resizable: bool,
+    pub high_dpi: bool,
+    /// Resampled to the 16, 32 and 64 pixel sizes the platform wants
+    pub icon: Option<Sprite>,
+    /// Calls to `GameLoop::fixed_update` per second
+    pub fixed_update_rate: f64,
+    /// Layers to create up front, including the back buffer. Extra layers start visible.
+    pub layers: usize,
+    /// How layer surfaces are sampled when the viewport stretches them
+    pub sample_filter: FilterMode,
+    pub pixel_cohesion: bool,
+    /// Start with `PGE::show_stats_overlay` on
+    pub stats_overlay: bool,
+}
+
+// the largest texture side most GPUs take
+const MAX_SCREEN_SIZE: usize = 16384;
+// every layer keeps a CPU copy, 8192x8192 is already 256MB each
+const MAX_SCREEN_PIXELS: usize = 8192 * 8192;
+
+impl EngineConfig {
+    pub fn new(title: &str, width: usize, height: usize) -> Self {
+        EngineConfig {
+            title: title.to_owned(),
+            width,
+            height,
+            pixel_width: 1,
+            pixel_height: 1,
+            fullscreen: false,
+            vsync: true,
+            resizable: true,
+            high_dpi: false,
+            icon: None,
+            fixed_update_rate: 60.0,
+            layers: 1,
+            sample_filter: FilterMode::Nearest,
+            pixel_cohesion: true,
+            stats_overlay: false,
+        }
+    }
+
+    pub fn pixel_size(mut self, width: usize, height: usize) -> Self {
+        self.pixel_width = width;
+        self.pixel_height = height;
+        self
+    }
+
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    pub fn high_dpi(mut self, high_dpi: bool) -> Self {
+        self.high_dpi = high_dpi;
+        self
+    }
+
+    pub fn icon(mut self, icon: Sprite) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    pub fn fixed_update_rate(mut self, rate: f64) -> Self {
+        self.fixed_update_rate = rate;
+        self
+    }
+
+    pub fn layers(mut self, layers: usize) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn sample_filter(mut self, filter: FilterMode) -> Self {
+        self.sample_filter = filter;
+        self
+    }
+
+    pub fn pixel_cohesion(mut self, cohesion: bool) -> Self {
+        self.pixel_cohesion = cohesion;
+        self
+    }
+
+    pub fn stats_overlay(mut self, show: bool) -> Self {
+        self.stats_overlay = show;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), PgeError> {
+        let (width, height) = (self.width, self.height);
+        if width == 0 || height == 0 || width > MAX_SCREEN_SIZE || height > MAX_SCREEN_SIZE
+            || width * height > MAX_SCREEN_PIXELS
+        {
+            return Err(PgeError::InvalidScreenSize { width, height });
+        }
+
+        let (pw, ph) = (self.pixel_width, self.pixel_height);
+        if pw == 0 || ph == 0 {
+            return Err(PgeError::InvalidPixelSize { width: pw, height: ph });
+        }
+
+        let window = (width.checked_mul(pw), height.checked_mul(ph));
+        match window {
+            (Some(w), Some(h)) if w <= i32::MAX as usize && h <= i32::MAX as usize => {}
+            _ => return Err(PgeError::InvalidWindowSize {
+                width: width.saturating_mul(pw),
+                height: height.saturating_mul(ph),
+            }),
+        }
+
+        time::tick_duration(self.fixed_update_rate)?;
+
+        if self.layers == 0 {
+            return Err(PgeError::InvalidLayerCount(self.layers));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn window_conf(&self) -> Conf {
+        let mut conf = Conf {
+            window_title: self.title.clone(),
+            window_width: (self.width * self.pixel_width) as i32,
+            window_height: (self.height * self.pixel_height) as i32,
+            fullscreen: self.fullscreen,
+            window_resizable: self.resizable,
+            high_dpi: self.high_dpi,
+            icon: self.icon.as_ref().map(window_icon),
+            ..Default::default()
+        };
+        conf.platform.swap_interval = Some(self.vsync as i32);
+        conf
+    }
+}
+
+fn window_icon(sprite: &Sprite) -> conf::Icon {
+    conf::Icon {
+        small: icon_pixels(sprite),
+        medium: icon_pixels(sprite),
+        big: icon_pixels(sprite),
+    }
+}
+
+// nearest neighbour resample into an N byte rgba square
+fn icon_pixels<const N: usize>(sprite: &Sprite) -> [u8; N] {
+    let size = ((N / 4) as f32).sqrt() as usize;
+    let mut bytes = [0; N];
+    for (i, rgba) in bytes.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % size, i / size);
+        let p = sprite.get_pixel((x * sprite.width as usize / size) as i32, (y * sprite.height as usize / size) as i32);
+        rgba.copy_from_slice(&[p.r, p.g, p.b, p.a]);
+    }
+    bytes
+}
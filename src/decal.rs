@@ -1,10 +1,10 @@
-use std::{rc::Weak, cell::RefCell};
+use std::{rc::Weak, cell::RefCell, path::Path};
 
 use miniquad::*;
 use glam::*;
 use crate::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DecalMode {
     Normal,
     Additive,
@@ -15,7 +15,7 @@ pub enum DecalMode {
     Model3D,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DecalStructure {
     Line,
     Fan,
@@ -23,10 +23,66 @@ pub enum DecalStructure {
     List,
 }
 
+/// One pipeline per blend mode for triangles and again for lines
+pub(crate) const DECAL_PIPELINES: usize = 10;
+
+impl DecalMode {
+    // source and destination factors, these follow olcPixelGameEngine's OpenGL renderer
+    pub(crate) fn blend_factors(&self) -> (BlendFactor, BlendFactor) {
+        use BlendFactor::*;
+        use BlendValue::*;
+        match self {
+            DecalMode::Normal | DecalMode::Wireframe | DecalMode::Model3D =>
+                (Value(SourceAlpha), OneMinusValue(SourceAlpha)),
+            DecalMode::Additive => (Value(SourceAlpha), One),
+            DecalMode::Multiplicative => (Value(DestinationColor), OneMinusValue(SourceAlpha)),
+            DecalMode::Stencil => (Zero, Value(SourceAlpha)),
+            DecalMode::Illuminate => (OneMinusValue(SourceAlpha), Value(SourceAlpha)),
+        }
+    }
+
+    pub(crate) fn blend_state(&self) -> BlendState {
+        let (src, dst) = self.blend_factors();
+        BlendState::new(Equation::Add, src, dst)
+    }
+
+    // modes that share blend factors share a pipeline
+    fn blend_index(&self) -> usize {
+        match self {
+            DecalMode::Normal | DecalMode::Wireframe | DecalMode::Model3D => 0,
+            DecalMode::Additive => 1,
+            DecalMode::Multiplicative => 2,
+            DecalMode::Stencil => 3,
+            DecalMode::Illuminate => 4,
+        }
+    }
+
+    /// Index of the pipeline drawing this mode as triangles, or as lines
+    pub(crate) fn pipeline_index(&self, lines: bool) -> usize {
+        self.blend_index() * 2 + lines as usize
+    }
+
+    /// Mode and primitive for the pipeline at `index`, the inverse of `pipeline_index`
+    pub(crate) fn pipeline_params(index: usize) -> (DecalMode, PrimitiveType) {
+        let mode = [
+            DecalMode::Normal,
+            DecalMode::Additive,
+            DecalMode::Multiplicative,
+            DecalMode::Stencil,
+            DecalMode::Illuminate,
+        ][index / 2];
+        let primitive = if index & 1 == 0 { PrimitiveType::Triangles } else { PrimitiveType::Lines };
+        (mode, primitive)
+    }
+}
+
 #[derive(Debug)]
 pub struct Decal {
     pub sprite: Weak<RefCell<Sprite>>,
     pub texture_id: TextureId,
+    /// Where the decal starts and how much of the texture it covers, in texture coordinates.
+    /// Decals from an atlas share their texture with others.
+    pub uv_offset: Vec2,
     pub uv_scale: Vec2,
     pub width: u32,
     pub height: u32,
@@ -43,12 +99,47 @@ impl Decal {
         (Decal {
             sprite: Rc::downgrade(&sprite_ref.0),   // create a weak pointer to the sprite_ref
             texture_id: id,
+            uv_offset: Vec2::ZERO,
             uv_scale: Vec2::ONE,
-            width: width,
-            height: height
+            width,
+            height
         }, sprite_ref)
     }
 
+    /// `Sprite::load` straight into a decal
+    pub fn load<P: AsRef<Path>>(pge: &mut PGE, path: P) -> Result<(Decal, SpriteRef), PgeError> {
+        Ok(Decal::new_from_sprite(pge, Sprite::load(path)?))
+    }
+
+    /// `Sprite::from_bytes` straight into a decal
+    pub fn from_bytes(pge: &mut PGE, bytes: &[u8]) -> Result<(Decal, SpriteRef), PgeError> {
+        Ok(Decal::new_from_sprite(pge, Sprite::from_bytes(bytes)?))
+    }
+
+    // a decal with no sprite behind it, used for the engine's own textures
+    pub(crate) fn new_detached(backend: &mut crate::Backend, sprite: &Sprite) -> Decal {
+        let id = backend.create_texture(sprite.width, sprite.height);
+        backend.update_texture(id, sprite);
+        Decal {
+            sprite: Weak::new(),
+            texture_id: id,
+            uv_offset: Vec2::ZERO,
+            uv_scale: Vec2::ONE,
+            width: sprite.width,
+            height: sprite.height
+        }
+    }
+
+    pub fn size(&self) -> Vec2 {
+        vec2(self.width as f32, self.height as f32)
+    }
+
+    // maps a pixel position inside the decal to a texture coordinate
+    #[inline]
+    pub(crate) fn pixel_to_uv(&self, p: Vec2) -> Vec2 {
+        self.uv_offset + p / self.size() * self.uv_scale
+    }
+
     // does not consume anything!
     pub fn new_from_sprite_ref(pge: &mut PGE, sprite_ref: &SpriteRef) -> Decal {
         let id = pge.create_texture(sprite_ref.width(), sprite_ref.height());
@@ -58,17 +149,64 @@ impl Decal {
         Decal {
             sprite: Rc::downgrade(&sprite_ref.0),
             texture_id: id,
+            uv_offset: Vec2::ZERO,
             uv_scale: Vec2::ONE,
-            width: width,
-            height: height
+            width,
+            height
         }
     }
 }
 
 #[derive(Debug)]
 pub struct DecalInstance {
+    pub texture_id: TextureId,
     pub vertices: Vec<Vertex>,
     pub tint: Color,
     pub mode: DecalMode,
     pub structure: DecalStructure,
+}
+
+impl DecalInstance {
+    /// Wireframes and line structures are drawn as a line list, everything else as triangles
+    pub(crate) fn is_lines(&self) -> bool {
+        self.mode == DecalMode::Wireframe || self.structure == DecalStructure::Line
+    }
+
+    pub(crate) fn pipeline_index(&self) -> usize {
+        self.mode.pipeline_index(self.is_lines())
+    }
+
+    // appends the triangle or line list for this instance, `base` is the index of its first vertex
+    pub(crate) fn triangulate(&self, base: u16, indices: &mut Vec<u16>) {
+        let n = self.vertices.len() as u16;
+        if self.mode == DecalMode::Wireframe {
+            // outline of the whole shape, closed back to the first vertex
+            for i in 0..n {
+                indices.extend_from_slice(&[base + i, base + (i + 1) % n]);
+            }
+            return;
+        }
+        match self.structure {
+            DecalStructure::Fan => {
+                for i in 1..n.saturating_sub(1) {
+                    indices.extend_from_slice(&[base, base + i, base + i + 1]);
+                }
+            }
+            DecalStructure::Strip => {
+                for i in 0..n.saturating_sub(2) {
+                    indices.extend_from_slice(&[base + i, base + i + 1, base + i + 2]);
+                }
+            }
+            DecalStructure::List => {
+                for i in (0..n - n % 3).step_by(3) {
+                    indices.extend_from_slice(&[base + i, base + i + 1, base + i + 2]);
+                }
+            }
+            DecalStructure::Line => {
+                for i in 0..n.saturating_sub(1) {
+                    indices.extend_from_slice(&[base + i, base + i + 1]);
+                }
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,79 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum PgeError {
+    /// The screen is empty or too large for a texture
+    InvalidScreenSize { width: usize, height: usize },
+    /// Pixels have to be at least 1x1 window pixels
+    InvalidPixelSize { width: usize, height: usize },
+    /// The window would be larger than the platform can describe
+    InvalidWindowSize { width: usize, height: usize },
+    /// Fixed updates need a finite rate above zero, in updates per second
+    InvalidTickRate(f64),
+    /// There is always at least the back buffer
+    InvalidLayerCount(usize),
+    /// `every_nth` and `downscale` start at 1
+    InvalidRecordingOptions,
+    /// Only one recording can run at a time
+    AlreadyRecording,
+    /// The encoder thread died without saying why
+    RecordingFailed,
+    /// Not a resource pack, a different key, or a truncated file
+    InvalidResourcePack,
+    /// A sprite sheet descriptor is missing something it needs
+    InvalidSpriteSheet(String),
+    /// The sprite does not fit on an atlas page
+    SpriteTooLarge { width: usize, height: usize },
+    /// An .ase or .aseprite file that could not be parsed
+    InvalidAseprite(String),
+    Io(std::io::Error),
+    /// Encoding or decoding an image failed
+    Image(image::ImageError),
+}
+
+impl fmt::Display for PgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgeError::InvalidScreenSize { width, height } =>
+                write!(f, "invalid screen size {}x{}", width, height),
+            PgeError::InvalidPixelSize { width, height } =>
+                write!(f, "invalid pixel size {}x{}", width, height),
+            PgeError::InvalidWindowSize { width, height } =>
+                write!(f, "window size {}x{} is too large", width, height),
+            PgeError::InvalidTickRate(rate) => write!(f, "invalid tick rate {}", rate),
+            PgeError::InvalidLayerCount(count) => write!(f, "invalid layer count {}", count),
+            PgeError::InvalidRecordingOptions => write!(f, "invalid recording options"),
+            PgeError::AlreadyRecording => write!(f, "a recording is already running"),
+            PgeError::RecordingFailed => write!(f, "the recording thread panicked"),
+            PgeError::InvalidResourcePack => write!(f, "invalid resource pack or wrong key"),
+            PgeError::InvalidSpriteSheet(reason) => write!(f, "invalid sprite sheet: {}", reason),
+            PgeError::SpriteTooLarge { width, height } =>
+                write!(f, "a {}x{} sprite does not fit on an atlas page", width, height),
+            PgeError::InvalidAseprite(reason) => write!(f, "invalid aseprite file: {}", reason),
+            PgeError::Io(e) => write!(f, "io error: {}", e),
+            PgeError::Image(e) => write!(f, "image error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PgeError::Io(e) => Some(e),
+            PgeError::Image(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PgeError {
+    fn from(e: std::io::Error) -> Self {
+        PgeError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for PgeError {
+    fn from(e: image::ImageError) -> Self {
+        PgeError::Image(e)
+    }
+}
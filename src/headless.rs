@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+
+use miniquad::*;
+use glam::*;
+use crate::*;
+
+/*
+    CPU stand-in for the GPU when running without a window. Textures are kept as
+    sprites and every layer is composited into a single frame sprite on render.
+*/
+
+pub struct Headless {
+    textures: HashMap<TextureId, Texture>,
+    next_texture: u32,
+    frame: Sprite,
+}
+
+struct Texture {
+    sprite: Sprite,
+    // how the texture is sampled between texel centres, like the GPU texture's min and mag filter
+    filter: FilterMode,
+}
+
+impl Headless {
+    pub fn new(width: u32, height: u32) -> Self {
+        Headless {
+            textures: HashMap::new(),
+            next_texture: 1,
+            frame: Sprite::new(width, height),
+        }
+    }
+
+    pub fn frame(&self) -> &Sprite {
+        &self.frame
+    }
+
+    pub fn into_frame(self) -> Sprite {
+        self.frame
+    }
+
+    pub fn texture(&self, id: TextureId) -> Option<&Sprite> {
+        self.textures.get(&id).map(|t| &t.sprite)
+    }
+
+    pub fn has_texture(&self, id: TextureId) -> bool {
+        self.textures.contains_key(&id)
+    }
+
+    /// Adds a texture under an id from elsewhere, e.g. a GPU texture read back for a screenshot.
+    /// `filter` should be the one the GPU texture samples with.
+    pub fn insert_texture(&mut self, id: TextureId, sprite: Sprite, filter: FilterMode) {
+        self.textures.insert(id, Texture { sprite, filter });
+    }
+
+    /// Creates a texture filtered linearly, like `Backend::create_texture` on the GPU
+    pub fn create_texture(&mut self, width: u32, height: u32) -> TextureId {
+        // the id is never handed to a real context, it only has to be unique
+        let id = TextureId::from_raw_id(RawId::OpenGl(self.next_texture));
+        self.next_texture += 1;
+        self.insert_texture(id, Sprite::new(width, height), FilterMode::Linear);
+        id
+    }
+
+    pub fn set_texture_filter(&mut self, id: TextureId, filter: FilterMode) {
+        if let Some(texture) = self.textures.get_mut(&id) {
+            texture.filter = filter;
+        }
+    }
+
+    pub fn update_texture(&mut self, id: TextureId, sprite: &Sprite) {
+        if let Some(Texture { sprite: texture, .. }) = self.textures.get_mut(&id) {
+            if texture.width == sprite.width && texture.height == sprite.height {
+                texture.pixel_data.copy_from_slice(&sprite.pixel_data);
+            }
+        }
+    }
+
+    pub fn update_texture_part(&mut self, id: TextureId, sprite: &Sprite, rect: &DirtyRect) {
+        if let Some(Texture { sprite: texture, .. }) = self.textures.get_mut(&id) {
+            if texture.width == sprite.width && texture.height == sprite.height {
+                let (x0, x1) = (rect.min.x as usize, rect.max.x as usize);
+                for y in rect.min.y as usize..rect.max.y as usize {
+                    let row = y * sprite.width as usize;
+                    texture.pixel_data[row + x0..row + x1].copy_from_slice(&sprite.pixel_data[row + x0..row + x1]);
+                }
+            }
+        }
+    }
+
+    pub fn read_texture(&self, id: TextureId, sprite: &mut Sprite) {
+        if let Some(texture) = self.texture(id) {
+            if texture.width == sprite.width && texture.height == sprite.height {
+                sprite.pixel_data.copy_from_slice(&texture.pixel_data);
+            }
+        }
+    }
+
+    pub fn delete_texture(&mut self, id: TextureId) {
+        self.textures.remove(&id);
+    }
+
+    // blends every visible layer back to front the same way the GPU pipeline does,
+    // the surface texture becomes a full screen quad moved by the layer's offset and scale.
+    // Only the texture filter is emulated, not mipmaps or the GPU's rounding.
+    pub fn composite<'a>(&mut self, layers: impl Iterator<Item = &'a Layer>) {
+        self.frame.clear(BLACK);
+
+        for layer in layers.filter(|l| l.show) {
+            let [uniforms] = layer.uniforms();
+            let quad = [
+                vert(vec2(-1.0,  1.0), vec2(0., 0.)),
+                vert(vec2(-1.0, -1.0), vec2(0., 1.)),
+                vert(vec2( 1.0, -1.0), vec2(1., 1.)),
+                vert(vec2( 1.0,  1.0), vec2(1., 0.)),
+            ].map(|v| transform(&v, &uniforms));
+
+            if let Some(surface) = self.textures.get(&layer.surface.decal.texture_id) {
+                for tri in [[0, 1, 2], [0, 2, 3]] {
+                    let v = tri.map(|i| &quad[i]);
+                    fill_textured_triangle(&mut self.frame, surface, layer.blend, v);
+                }
+            }
+            self.draw_decals(&layer.decal_instances, &uniforms);
+        }
+
+        // the window shows the back buffer opaque whatever alpha the blending left in it
+        for p in &mut self.frame.pixel_data {
+            p.a = 255;
+        }
+    }
+
+    fn draw_decals(&mut self, instances: &[DecalInstance], uniforms: &UniformData) {
+        let mut indices = Vec::new();
+        let mut vertices = Vec::new();
+        for di in instances {
+            let Some(texture) = self.textures.get(&di.texture_id) else { continue };
+            indices.clear();
+            di.triangulate(0, &mut indices);
+            vertices.clear();
+            vertices.extend(di.vertices.iter().map(|v| transform(v, uniforms)));
+            if di.is_lines() {
+                for line in indices.chunks_exact(2) {
+                    let v = [&vertices[line[0] as usize], &vertices[line[1] as usize]];
+                    draw_textured_line(&mut self.frame, texture, di.mode, v);
+                }
+            } else {
+                for tri in indices.chunks_exact(3) {
+                    let v = [
+                        &vertices[tri[0] as usize],
+                        &vertices[tri[1] as usize],
+                        &vertices[tri[2] as usize],
+                    ];
+                    fill_textured_triangle(&mut self.frame, texture, di.mode, v);
+                }
+            }
+        }
+    }
+}
+
+// what the vertex shader does with the layer uniforms, the tint is folded into the colour
+#[inline]
+fn transform(v: &Vertex, uniforms: &UniformData) -> Vertex {
+    Vertex { pos: uniforms.transform(v.pos), col: v.col * uniforms.tint, ..v.clone() }
+}
+
+#[inline]
+fn to_frame(frame: &Sprite, pos: Vec2) -> Vec2 {
+    vec2((pos.x + 1.0) * 0.5 * frame.width as f32, (1.0 - pos.y) * 0.5 * frame.height as f32)
+}
+
+#[inline]
+fn shade(frame: &mut Sprite, texture: &Texture, mode: DecalMode, x: u32, y: u32, uv: Vec2, col: Vec4) {
+    let texel = sample(texture, uv) * col * 255.0;
+    let src = Pixel::rgba(texel.x as u8, texel.y as u8, texel.z as u8, texel.w as u8);
+    let dst = &mut frame.pixel_data[(y * frame.width + x) as usize];
+    *dst = blend(mode, &src, dst);
+}
+
+// textures are created with clamp to edge wrapping
+fn sample(texture: &Texture, uv: Vec2) -> Vec4 {
+    let sprite = &texture.sprite;
+    let size = vec2(sprite.width as f32, sprite.height as f32);
+    let texel = |x: i32, y: i32| {
+        let (x, y) = (x.clamp(0, sprite.width as i32 - 1), y.clamp(0, sprite.height as i32 - 1));
+        sprite.pixel_data[(y * sprite.width as i32 + x) as usize].to_vec4()
+    };
+    match texture.filter {
+        FilterMode::Nearest => {
+            let p = (uv * size).floor();
+            texel(p.x as i32, p.y as i32)
+        }
+        FilterMode::Linear => {
+            // texel centres sit at half coordinates
+            let p = uv * size - 0.5;
+            let (x, y) = (p.x.floor() as i32, p.y.floor() as i32);
+            let f = p - p.floor();
+            let top = texel(x, y).lerp(texel(x + 1, y), f.x);
+            let bottom = texel(x, y + 1).lerp(texel(x + 1, y + 1), f.x);
+            top.lerp(bottom, f.y)
+        }
+    }
+}
+
+// rasterizes a triangle given in normalized device coordinates, sampling pixel centres
+fn fill_textured_triangle(frame: &mut Sprite, texture: &Texture, mode: DecalMode, v: [&Vertex; 3]) {
+    let (fw, fh) = (frame.width as f32, frame.height as f32);
+    let p: Vec<Vec2> = v.iter().map(|v| to_frame(frame, v.pos)).collect();
+
+    let area = (p[1] - p[0]).perp_dot(p[2] - p[0]);
+    if area == 0.0 {
+        return;
+    }
+
+    let min = p[0].min(p[1]).min(p[2]).max(Vec2::ZERO);
+    let max = p[0].max(p[1]).max(p[2]).min(vec2(fw, fh));
+
+    for y in min.y.floor() as u32..max.y.ceil() as u32 {
+        for x in min.x.floor() as u32..max.x.ceil() as u32 {
+            let c = vec2(x as f32 + 0.5, y as f32 + 0.5);
+            let w0 = (p[2] - p[1]).perp_dot(c - p[1]) / area;
+            let w1 = (p[0] - p[2]).perp_dot(c - p[2]) / area;
+            let w2 = 1.0 - w0 - w1;
+            if w0 < 0.0 || w1 < 0.0 || w2 < 0.0 {
+                continue;
+            }
+
+            // perspective correct, the same as the GPU does with the vertex w
+            let (p0, p1, p2) = (w0 / v[0].w, w1 / v[1].w, w2 / v[2].w);
+            let sum = p0 + p1 + p2;
+            let (w0, w1, w2) = (p0 / sum, p1 / sum, p2 / sum);
+
+            let uv = v[0].uv * w0 + v[1].uv * w1 + v[2].uv * w2;
+            let col = v[0].col * w0 + v[1].col * w1 + v[2].col * w2;
+            shade(frame, texture, mode, x, y, uv, col);
+        }
+    }
+}
+
+// steps one pixel at a time along the major axis
+fn draw_textured_line(frame: &mut Sprite, texture: &Texture, mode: DecalMode, v: [&Vertex; 2]) {
+    let p0 = to_frame(frame, v[0].pos);
+    let p1 = to_frame(frame, v[1].pos);
+    let steps = (p1 - p0).abs().max_element().ceil().max(1.0) as u32;
+
+    for i in 0..steps {
+        let t = (i as f32 + 0.5) / steps as f32;
+        let p = p0.lerp(p1, t);
+        if p.x < 0.0 || p.y < 0.0 || p.x >= frame.width as f32 || p.y >= frame.height as f32 {
+            continue;
+        }
+        let uv = v[0].uv.lerp(v[1].uv, t);
+        let col = v[0].col.lerp(v[1].col, t);
+        shade(frame, texture, mode, p.x as u32, p.y as u32, uv, col);
+    }
+}
+
+// evaluates the same blend equation the GPU pipeline for `mode` uses
+#[inline]
+fn blend(mode: DecalMode, src: &Pixel, dst: &Pixel) -> Pixel {
+    let s = src.to_vec4();
+    let d = dst.to_vec4();
+    let factor = |f: BlendFactor| match f {
+        BlendFactor::Zero => Vec4::ZERO,
+        BlendFactor::One => Vec4::ONE,
+        BlendFactor::Value(v) => blend_value(v, s, d),
+        BlendFactor::OneMinusValue(v) => Vec4::ONE - blend_value(v, s, d),
+        BlendFactor::SourceAlphaSaturate => {
+            let f = s.w.min(1.0 - d.w);
+            vec4(f, f, f, 1.0)
+        }
+    };
+    let (fs, fd) = mode.blend_factors();
+    let out = ((s * factor(fs) + d * factor(fd)).clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round();
+    Pixel::rgba(out.x as u8, out.y as u8, out.z as u8, out.w as u8)
+}
+
+#[inline]
+fn blend_value(value: BlendValue, src: Vec4, dst: Vec4) -> Vec4 {
+    match value {
+        BlendValue::SourceColor => src,
+        BlendValue::SourceAlpha => Vec4::splat(src.w),
+        BlendValue::DestinationColor => dst,
+        BlendValue::DestinationAlpha => Vec4::splat(dst.w),
+    }
+}
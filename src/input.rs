@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+pub use miniquad::{KeyCode, MouseButton};
+
+/// State of a key or button for the current frame
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HWButton {
+    /// Set once on the frame the button goes down
+    pub pressed: bool,
+    /// Set once on the frame the button goes up
+    pub released: bool,
+    /// Set for as long as the button is down
+    pub held: bool,
+}
+
+impl HWButton {
+    pub(crate) fn press(&mut self) {
+        if !self.held {
+            self.pressed = true;
+        }
+        self.held = true;
+    }
+
+    pub(crate) fn release(&mut self) {
+        if self.held {
+            self.released = true;
+        }
+        self.held = false;
+    }
+
+    // pressed and released only last a single frame
+    pub(crate) fn end_frame(&mut self) {
+        self.pressed = false;
+        self.released = false;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    keys: HashMap<KeyCode, HWButton>,
+}
+
+impl Keyboard {
+    pub fn get(&self, key: KeyCode) -> HWButton {
+        self.keys.get(&key).copied().unwrap_or_default()
+    }
+
+    pub(crate) fn key_down(&mut self, key: KeyCode) {
+        self.keys.entry(key).or_default().press();
+    }
+
+    pub(crate) fn key_up(&mut self, key: KeyCode) {
+        self.keys.entry(key).or_default().release();
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        for key in self.keys.values_mut() {
+            key.end_frame();
+        }
+    }
+
+    // key up events are not guaranteed once the window loses focus
+    pub(crate) fn release_all(&mut self) {
+        for key in self.keys.values_mut() {
+            key.release();
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Mouse {
+    buttons: [HWButton; 3],
+    // wheel movement accumulated since the last frame
+    pub(crate) wheel: f32,
+}
+
+impl Mouse {
+    pub fn get(&self, button: MouseButton) -> HWButton {
+        match Mouse::index(button) {
+            Some(i) => self.buttons[i],
+            None => HWButton::default(),
+        }
+    }
+
+    pub(crate) fn button_down(&mut self, button: MouseButton) {
+        if let Some(i) = Mouse::index(button) {
+            self.buttons[i].press();
+        }
+    }
+
+    pub(crate) fn button_up(&mut self, button: MouseButton) {
+        if let Some(i) = Mouse::index(button) {
+            self.buttons[i].release();
+        }
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        for button in &mut self.buttons {
+            button.end_frame();
+        }
+        self.wheel = 0.0;
+    }
+
+    pub(crate) fn release_all(&mut self) {
+        for button in &mut self.buttons {
+            button.release();
+        }
+    }
+
+    fn index(button: MouseButton) -> Option<usize> {
+        match button {
+            MouseButton::Left => Some(0),
+            MouseButton::Middle => Some(1),
+            MouseButton::Right => Some(2),
+            MouseButton::Unknown => None,
+        }
+    }
+}
+
+/*
+    Collects typed characters while enabled, like olc's TextEntryEnable.
+    The cursor is a byte offset into text and always sits on a char boundary.
+*/
+#[derive(Debug, Default)]
+pub struct TextEntry {
+    pub enabled: bool,
+    pub text: String,
+    pub cursor: usize,
+    // set when enter is pressed, handed to the game on the next update
+    pub(crate) completed: Option<String>,
+}
+
+impl TextEntry {
+    pub(crate) fn enable(&mut self, enable: bool, text: &str) {
+        self.enabled = enable;
+        if enable {
+            self.text = text.to_owned();
+            self.cursor = self.text.len();
+        }
+    }
+
+    pub(crate) fn char_typed(&mut self, c: char) {
+        if self.enabled && !c.is_control() {
+            self.text.insert(self.cursor, c);
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    pub(crate) fn key_down(&mut self, key: KeyCode) {
+        if !self.enabled {
+            return;
+        }
+        match key {
+            KeyCode::Backspace => {
+                if let Some(c) = self.text[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                    self.text.remove(self.cursor);
+                }
+            }
+            KeyCode::Delete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            KeyCode::Left => {
+                if let Some(c) = self.text[..self.cursor].chars().next_back() {
+                    self.cursor -= c.len_utf8();
+                }
+            }
+            KeyCode::Right => {
+                if let Some(c) = self.text[self.cursor..].chars().next() {
+                    self.cursor += c.len_utf8();
+                }
+            }
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = self.text.len(),
+            KeyCode::Enter | KeyCode::KpEnter => {
+                self.enabled = false;
+                self.completed = Some(self.text.clone());
+            }
+            _ => {}
+        }
+    }
+}
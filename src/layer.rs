@@ -6,37 +6,303 @@ use crate::{decal::DecalInstance, *};
     Each layer should be almost fully self contained
 */
 
+/*
+    Layers are composited back to front in index order, so layer 0 (the back buffer)
+    is at the back and every layer after it is drawn on top. Offset, scale and tint
+    apply to everything on the layer, its sprite surface and its decals.
+*/
+
 pub struct Layer {
+    /// Shift of the layer as a fraction of the screen size, positive y is down
     pub offset: Vec2,
+    /// Size of the layer relative to the screen, scaled from the top left corner
     pub scale: Vec2,
     pub show: bool,
+    /// The surface is only uploaded while this is set, see `PGE::set_draw_target`
     pub update: bool,
+    /// Part of the surface written since its last upload. The drawing routines keep this
+    /// up to date, anything writing to the sprite directly has to call `mark_dirty_all`.
+    pub dirty: Option<DirtyRect>,
     pub surface: Renderable,
     pub decal_instances: Vec<DecalInstance>,
     pub tint: Color,
+    /// How the layer's sprite surface blends with the layers behind it
+    pub blend: DecalMode,
     pub id: usize,
-    pub pipeline: Pipeline,
-    pub bindings: Bindings,
+    /// Draws the layer on the GPU, `None` when running headless
+    pub renderer: Option<Box<dyn LayerRenderer>>,
+}
+
+/// What a renderer gets to see of its layer each frame
+pub struct LayerFrame<'a> {
+    /// CPU side sprite the drawing routines write to
+    pub surface: &'a SpriteRef,
+    /// Part of the surface that has to be uploaded this frame, `None` when it is unchanged
+    /// or the layer's `update` flag is off
+    pub dirty: Option<DirtyRect>,
+    /// Decals queued on the layer this frame, in submission order
+    pub decals: &'a [DecalInstance],
+    /// The layer's offset, scale and tint, ready for `apply_uniforms`
     pub uniforms: [UniformData; 1],
+    pub blend: DecalMode,
+    /// The engine's shader and pipelines, custom renderers can draw with them too
+    pub resources: &'a LayerResources,
+}
+
+/// Draws a layer. `SpriteRenderer` is the default, it shows the layer's sprite surface
+/// with its decals on top, other implementations can draw anything on the GPU.
+/// Closures taking `(ctx, layer)` are renderers too, see `PGE::set_layer_custom_render_function`.
+#[allow(unused_variables)]
+pub trait LayerRenderer {
+    /// Called every frame before the render pass begins, upload textures or draw offscreen here
+    fn prepare(&mut self, ctx: &mut dyn RenderingBackend, layer: &LayerFrame) {}
+    /// Called inside the frame's default render pass, layers are drawn in order
+    fn render(&mut self, ctx: &mut dyn RenderingBackend, layer: &LayerFrame);
+    /// Called when the window changes size, in physical pixels
+    fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: f32, height: f32) {}
+}
+
+impl<F> LayerRenderer for F where F: FnMut(&mut dyn RenderingBackend, &LayerFrame) {
+    fn render(&mut self, ctx: &mut dyn RenderingBackend, layer: &LayerFrame) {
+        self(ctx, layer);
+    }
+}
+
+/// Pixel rectangle of a layer surface, `max` is exclusive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirtyRect {
+    pub min: UVec2,
+    pub max: UVec2,
+}
+
+impl DirtyRect {
+    pub fn size(&self) -> UVec2 {
+        self.max - self.min
+    }
+
+    /// Size of the rectangle's rgba pixels
+    pub fn bytes(&self) -> usize {
+        let size = self.size();
+        size.x as usize * size.y as usize * 4
+    }
+
+    #[inline]
+    fn include(&mut self, x: u32, y: u32) {
+        self.min = self.min.min(uvec2(x, y));
+        self.max = self.max.max(uvec2(x + 1, y + 1));
+    }
 }
 
+/// The default renderer, it only owns the texture the layer's sprite is uploaded to
+pub struct SpriteRenderer {
+    pub texture: TextureId,
+}
+
+/// GPU objects every layer draws with, created once per engine
+pub struct LayerResources {
+    pub shader: ShaderId,
+    /// One per blend mode and primitive, indexed by `DecalMode::pipeline_index`
+    pub pipelines: Vec<Pipeline>,
+    /// A full screen quad, drawn with the first 6 of `quad_indices`
+    pub quad_vertices: BufferId,
+    /// Two triangles for every 4 vertices
+    pub quad_indices: BufferId,
+    /// Streamed with each layer's decals as it is drawn
+    pub decal_vertices: BufferId,
+    pub decal_indices: BufferId,
+}
+
+/// Decal vertices uploaded at once, layers with more decals draw them in several batches
+pub const MAX_DECAL_VERTICES: usize = u16::MAX as usize;
+const MAX_DECAL_INDICES: usize = MAX_DECAL_VERTICES * 3;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct UniformData {
     pub tint: Vec4,
+    /// Translation in normalized device coordinates, applied after `scale`
     pub offset: Vec2,
+    pub scale: Vec2,
+}
+
+impl UniformData {
+    // maps the top left anchored, y down screen transform into normalized device coordinates
+    pub fn new(offset: Vec2, scale: Vec2, tint: &Color) -> Self {
+        UniformData {
+            tint: tint.to_vec4(),
+            offset: vec2(scale.x - 1.0 + 2.0 * offset.x, 1.0 - scale.y - 2.0 * offset.y),
+            scale,
+        }
+    }
+
+    /// Where a vertex given in normalized device coordinates ends up
+    pub fn transform(&self, pos: Vec2) -> Vec2 {
+        pos * self.scale + self.offset
+    }
+}
+
+// the vertex struct is padded past its last attribute so the stride has to be explicit
+fn vertex_layout() -> BufferLayout {
+    BufferLayout { stride: std::mem::size_of::<Vertex>() as i32, ..Default::default() }
 }
 
 impl Layer {
     pub fn new(pge: &mut PGE, width: u32, height: u32) -> Self {
-        let cpu_bb = SpriteRef::new(width as u32, height as u32);
+        let cpu_bb = SpriteRef::new(width, height);
+        let cpu_bb_weak_ref = Rc::downgrade(&cpu_bb.0);
+
+        let (cpu_bb_tex, renderer) = match &mut pge.backend {
+            Backend::Gpu(ctx) => {
+                let renderer = SpriteRenderer::new(ctx.as_mut(), &cpu_bb, width, height, pge.sample_filter);
+                (renderer.texture, Some(Box::new(renderer) as Box<dyn LayerRenderer>))
+            }
+            Backend::Headless(headless) => {
+                let texture = headless.create_texture(width, height);
+                headless.set_texture_filter(texture, pge.sample_filter);
+                (texture, None)
+            }
+        };
+
+        Layer {
+            offset: Vec2::ZERO,
+            scale: Vec2::ONE,
+            tint: WHITE,
+            blend: DecalMode::Normal,
+            show: false,
+            update: false,
+            dirty: None,
+            surface: Renderable { 
+                sprite: cpu_bb,
+                decal: Decal { 
+                    sprite: cpu_bb_weak_ref,
+                    texture_id: cpu_bb_tex,
+                    uv_offset: Vec2::ZERO,
+                    uv_scale: Vec2::ONE,
+                    width,
+                    height,
+                }},
+            decal_instances: vec![],
+            id: pge.layers.len(),
+            renderer,
+        }
+    }
+
+    /// A layer drawn by a custom renderer instead of `SpriteRenderer`. It still has a sprite
+    /// surface of the given size, which the renderer can use or ignore.
+    pub fn with_renderer(pge: &mut PGE, width: u32, height: u32, renderer: Box<dyn LayerRenderer>) -> Self {
+        let mut layer = Layer::new(pge, width, height);
+        if layer.renderer.is_some() {
+            layer.renderer = Some(renderer);
+        }
+        layer
+    }
+
+    /// Grows the dirty rectangle to cover the pixel, positions outside the surface are ignored
+    #[inline]
+    pub fn mark_dirty(&mut self, x: i32, y: i32) {
+        let (w, h) = (self.surface.decal.width, self.surface.decal.height);
+        if x < 0 || y < 0 || x as u32 >= w || y as u32 >= h {
+            return;
+        }
+        let (x, y) = (x as u32, y as u32);
+        match &mut self.dirty {
+            Some(rect) => rect.include(x, y),
+            None => self.dirty = Some(DirtyRect { min: uvec2(x, y), max: uvec2(x + 1, y + 1) }),
+        }
+    }
+
+    pub fn mark_dirty_all(&mut self) {
+        let size = uvec2(self.surface.decal.width, self.surface.decal.height);
+        self.dirty = Some(DirtyRect { min: UVec2::ZERO, max: size });
+    }
+
+    // hands the dirty rectangle to whoever uploads the surface, it is kept while `update` is off
+    pub(crate) fn take_dirty(&mut self) -> Option<DirtyRect> {
+        if self.update { self.dirty.take() } else { None }
+    }
+
+    /// The offset, scale and tint fields as shader uniforms
+    pub fn uniforms(&self) -> [UniformData; 1] {
+        [UniformData::new(self.offset, self.scale, &self.tint)]
+    }
+
+    /// Returns how many bytes of the surface the renderer was asked to upload
+    pub fn prepare(&mut self, ctx: &mut dyn RenderingBackend, resources: &LayerResources) -> usize {
+        let dirty = self.take_dirty();
+        let (frame, renderer) = self.split(dirty, resources);
+        if let Some(renderer) = renderer {
+            renderer.prepare(ctx, &frame);
+        }
+        dirty.map_or(0, |rect| rect.bytes())
+    }
+
+    pub fn render(&mut self, ctx: &mut dyn RenderingBackend, resources: &LayerResources) {
+        let (frame, renderer) = self.split(None, resources);
+        if let Some(renderer) = renderer {
+            renderer.render(ctx, &frame);
+        }
+    }
+
+    // borrows the fields a renderer gets to see apart from the renderer itself, so both can be used at once
+    fn split<'a>(&'a mut self, dirty: Option<DirtyRect>, resources: &'a LayerResources) -> (LayerFrame<'a>, Option<&'a mut Box<dyn LayerRenderer>>) {
+        let frame = LayerFrame {
+            surface: &self.surface.sprite,
+            dirty,
+            decals: &self.decal_instances,
+            uniforms: self.uniforms(),
+            blend: self.blend,
+            resources,
+        };
+        (frame, self.renderer.as_mut())
+    }
+
+    pub fn resize(&mut self, ctx: &mut dyn RenderingBackend, width: f32, height: f32) {
+        if let Some(renderer) = &mut self.renderer {
+            renderer.resize(ctx, width, height);
+        }
+    }
+}
+
+impl SpriteRenderer {
+    pub fn new(ctx: &mut dyn RenderingBackend, cpu_bb: &SpriteRef, width: u32, height: u32, filter: FilterMode) -> Self {
         let cpu_bb_len = cpu_bb.get_data_len();
 
-        let cpu_bb_tex = pge.ctx.new_texture_from_rgba8(width as u16, height as u16, unsafe {
+        let texture = ctx.new_texture_from_rgba8(width as u16, height as u16, unsafe {
             std::slice::from_raw_parts(cpu_bb.get_data_ptr(), cpu_bb_len * 4)
         });
 
-        pge.ctx.texture_set_filter(cpu_bb_tex, FilterMode::Nearest, MipmapFilterMode::None);
-        let cpu_bb_weak_ref = Rc::downgrade(&cpu_bb.0);
+        ctx.texture_set_filter(texture, filter, MipmapFilterMode::None);
+
+        SpriteRenderer { texture }
+    }
+}
+
+impl LayerRenderer for SpriteRenderer {
+    fn prepare(&mut self, ctx: &mut dyn RenderingBackend, layer: &LayerFrame) {
+        let Some(rect) = layer.dirty else { return };
+        let sprite = layer.surface.get_sprite();
+        let (pos, size) = (rect.min.as_ivec2(), rect.size().as_ivec2());
+        ctx.texture_update_part(self.texture, pos.x, pos.y, size.x, size.y, &dirty_bytes(&sprite, &rect));
+    }
+
+    fn render(&mut self, ctx: &mut dyn RenderingBackend, layer: &LayerFrame) {
+        let res = layer.resources;
+        ctx.apply_pipeline(&res.pipelines[layer.blend.pipeline_index(false)]);
+        ctx.apply_bindings(&Bindings {
+            vertex_buffers: vec![res.quad_vertices],
+            index_buffer: res.quad_indices,
+            images: vec![self.texture],
+        });
+        ctx.apply_uniforms(UniformsSource::table(&layer.uniforms));
+        ctx.draw(0, 6, 1);
+
+        // decals go on top of the layer's sprite surface
+        res.draw_decals(ctx, layer.decals, &layer.uniforms);
+    }
+}
 
+impl LayerResources {
+    pub fn new(ctx: &mut dyn RenderingBackend) -> Self {
         // screen space vertex's for 2 triangles
         let vertices: [Vertex; 4] = [
             vert(vec2(-1.0, -1.0), vec2(0., 1.)),
@@ -45,42 +311,40 @@ impl Layer {
             vert(vec2(-1.0,  1.0), vec2(0., 0.)),
         ];
 
-        let vertex_buffer = pge.ctx.new_buffer(
+        let quad_vertices = ctx.new_buffer(
             BufferType::VertexBuffer,
-            BufferUsage::Dynamic,
+            BufferUsage::Immutable,
             BufferSource::slice(&vertices),
         );
 
         let mut indices = Vec::with_capacity(u16::MAX as usize);
         for i in (0..u16::MAX).step_by(4) {
-            indices.push(i + 0);
+            indices.push(i);
             indices.push(i + 1);
             indices.push(i + 2);
-            indices.push(i + 0);
+            indices.push(i);
             indices.push(i + 2);
             indices.push(i + 3);
         }
         
-        let index_buffer = pge.ctx.new_buffer(
+        let quad_indices = ctx.new_buffer(
             BufferType::IndexBuffer,
             BufferUsage::Immutable,
             BufferSource::slice(&indices),
         );
 
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer: index_buffer,
-            images: vec![cpu_bb_tex],
-        };
-
-        // NOTE: Below is how we update our vertex buffer.
-        //      This is why we set the BufferUsage to Dynamic
-        //      Index buffer is pre filled out
-        // pge.ctx.buffer_update(bindings.vertex_buffers[0], data)
+        let decal_vertices = ctx.new_buffer(
+            BufferType::VertexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<Vertex>(MAX_DECAL_VERTICES),
+        );
+        let decal_indices = ctx.new_buffer(
+            BufferType::IndexBuffer,
+            BufferUsage::Stream,
+            BufferSource::empty::<u16>(MAX_DECAL_INDICES),
+        );
 
-        // TODO: need to change this to reference a shader inside this file or included with
-        //      include! macro
-        let shader = pge.ctx
+        let shader = ctx
             .new_shader(
                 ShaderSource::Glsl {
                     vertex: shader::GL_VERTEX,
@@ -90,66 +354,169 @@ impl Layer {
             )
             .unwrap();
 
-        // TODO: add offset, scale, tint, into the shader as variables
-        let params = PipelineParams {
-            color_blend: Some(BlendState::new(
-                Equation::Add,
-                BlendFactor::Value(BlendValue::SourceAlpha),
-                BlendFactor::OneMinusValue(BlendValue::SourceAlpha))
-            ),
-            ..Default::default()
+        let pipelines = (0..DECAL_PIPELINES).map(|i| {
+            let (mode, primitive_type) = DecalMode::pipeline_params(i);
+            ctx.new_pipeline(
+                &[vertex_layout()],
+                &[
+                    VertexAttribute::new("in_pos", VertexFormat::Float2),
+                    VertexAttribute::new("in_uv", VertexFormat::Float2),
+                    VertexAttribute::new("in_col", VertexFormat::Float4),
+                    VertexAttribute::new("in_w", VertexFormat::Float1),
+                ],
+                shader,
+                PipelineParams {
+                    color_blend: Some(mode.blend_state()),
+                    primitive_type,
+                    ..Default::default()
+                })
+        }).collect();
+
+        LayerResources {
+            shader,
+            pipelines,
+            quad_vertices,
+            quad_indices,
+            decal_vertices,
+            decal_indices,
+        }
+    }
+
+    // Consecutive instances that share a texture and pipeline are merged into one draw call.
+    // Instances are never reordered so blending stays in submission order.
+    pub fn draw_decals(&self, ctx: &mut dyn RenderingBackend, instances: &[DecalInstance], uniforms: &[UniformData; 1]) {
+        let mut bindings = Bindings {
+            vertex_buffers: vec![self.decal_vertices],
+            index_buffer: self.decal_indices,
+            images: Vec::new(),
         };
+        for batch in decal_batches(instances) {
+            // the buffers are shared by every layer and batch, GL orders these updates with the draws before them
+            ctx.buffer_update(self.decal_vertices, BufferSource::slice(&batch.vertices));
+            ctx.buffer_update(self.decal_indices, BufferSource::slice(&batch.indices));
 
-        let pipeline = pge.ctx.new_pipeline_with_params(
-            &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("in_pos", VertexFormat::Float2),
-                VertexAttribute::new("in_uv", VertexFormat::Float2),
-                // TODO: add per triangle stuff here?
+            for draw in batch.draws {
+                // uniforms belong to the applied pipeline so they go again after every switch
+                ctx.apply_pipeline(&self.pipelines[draw.pipeline]);
+                ctx.apply_uniforms(UniformsSource::table(uniforms));
+                bindings.images = vec![draw.texture_id];
+                ctx.apply_bindings(&bindings);
+                ctx.draw(draw.first as i32, draw.count as i32, 1);
+            }
+        }
+    }
+}
 
-            ],
-            shader,
-            params
-        );
+// one upload of the decal buffers and the draws out of it
+struct DecalBatch {
+    vertices: Vec<Vertex>,
+    indices: Vec<u16>,
+    draws: Vec<DecalDraw>,
+}
 
-        Layer {
-            offset: Vec2::ZERO,
-            scale: Vec2::ONE,
+struct DecalDraw {
+    texture_id: TextureId,
+    pipeline: usize,
+    first: usize,
+    count: usize,
+}
+
+// fills the decal buffers as far as they go and starts over when they are full
+fn decal_batches(instances: &[DecalInstance]) -> Vec<DecalBatch> {
+    let mut batches = Vec::new();
+    let mut batch = DecalBatch { vertices: Vec::new(), indices: Vec::new(), draws: Vec::new() };
+
+    for di in instances {
+        // could never be indexed with u16
+        if di.vertices.len() > MAX_DECAL_VERTICES {
+            continue;
+        }
+        if batch.vertices.len() + di.vertices.len() > MAX_DECAL_VERTICES {
+            let full = std::mem::replace(&mut batch, DecalBatch { vertices: Vec::new(), indices: Vec::new(), draws: Vec::new() });
+            batches.push(full);
+        }
+
+        let first = batch.indices.len();
+        di.triangulate(batch.vertices.len() as u16, &mut batch.indices);
+        batch.vertices.extend_from_slice(&di.vertices);
+        let count = batch.indices.len() - first;
+        if count == 0 {
+            continue;
+        }
+        let pipeline = di.pipeline_index();
+
+        match batch.draws.last_mut() {
+            Some(draw) if draw.texture_id == di.texture_id && draw.pipeline == pipeline => draw.count += count,
+            _ => batch.draws.push(DecalDraw { texture_id: di.texture_id, pipeline, first, count }),
+        }
+    }
+
+    batches.push(batch);
+    batches.retain(|b| !b.draws.is_empty());
+    batches
+}
+
+// the pixels under `rect` packed row after row, as texture_update_part wants them.
+// Full width rectangles are already contiguous in the sprite and are not copied.
+pub(crate) fn dirty_bytes<'a>(sprite: &'a Sprite, rect: &DirtyRect) -> std::borrow::Cow<'a, [u8]> {
+    let width = sprite.width as usize;
+    let (x0, x1) = (rect.min.x as usize, rect.max.x as usize);
+    let rows = rect.min.y as usize..rect.max.y as usize;
+    let bytes = unsafe {
+        std::slice::from_raw_parts(sprite.pixel_data.as_ptr() as *const u8, sprite.pixel_data.len() * 4)
+    };
+    if x0 == 0 && x1 == width {
+        return bytes[rows.start * width * 4..rows.end * width * 4].into();
+    }
+    let mut packed = Vec::with_capacity((x1 - x0) * rows.len() * 4);
+    for y in rows {
+        packed.extend_from_slice(&bytes[(y * width + x0) * 4..(y * width + x1) * 4]);
+    }
+    packed.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quad(texture: u32) -> DecalInstance {
+        DecalInstance {
+            texture_id: TextureId::from_raw_id(RawId::OpenGl(texture)),
+            vertices: vec![vert(Vec2::ZERO, Vec2::ZERO); 4],
             tint: WHITE,
-            show: false,
-            update: false,
-            surface: Renderable { 
-                sprite: cpu_bb,
-                decal: Decal { 
-                    sprite: cpu_bb_weak_ref,
-                    texture_id: cpu_bb_tex,
-                    uv_scale: Vec2::ONE,
-                    width: width as u32,
-                    height: height as u32,
-                }},
-            decal_instances: vec![],
-            id: 1,  // TODO: not used
-            pipeline,
-            bindings,
-            // These are per layer and applied to everything on the layer
-            uniforms: [UniformData { tint: vec4(1.,0.,0.,0.), offset: vec2(-0.1, -0.1) }],
+            mode: DecalMode::Normal,
+            structure: DecalStructure::Fan,
         }
     }
 
-    pub fn render(&mut self, ctx: &mut Box<dyn RenderingBackend>) {
-        // always update the back buffer image (it's always index 0)
-        ctx.texture_update(self.bindings.images[0], unsafe {
-            let len = self.surface.sprite.get_data_len();
-            std::slice::from_raw_parts(self.surface.sprite.get_data_ptr(), len * 4)
-        });
+    #[test]
+    fn decals_past_the_buffer_go_in_another_batch() {
+        // 80000 vertices, the texture switches after the first buffer is full
+        let instances: Vec<DecalInstance> = (0..20000).map(|i| quad(1 + (i >= 18000) as u32)).collect();
+        let batches = decal_batches(&instances);
+        assert_eq!(batches.len(), 2);
 
-        ctx.begin_default_pass(PassAction::Nothing);//Default::default());
-        ctx.apply_pipeline(&self.pipeline);
-        ctx.apply_bindings(&self.bindings);
-        ctx.apply_uniforms_from_bytes(self.uniforms.as_ptr() as *const u8, 1);
-        ctx.draw(0, 6, 1);
-        ctx.end_render_pass();
+        let mut drawn = 0;
+        for batch in &batches {
+            assert!(batch.vertices.len() <= MAX_DECAL_VERTICES);
+            assert!(batch.indices.iter().all(|&i| (i as usize) < batch.vertices.len()));
+            // the draws cover the indices in order
+            let mut next = 0;
+            for draw in &batch.draws {
+                assert_eq!(draw.first, next);
+                next += draw.count;
+            }
+            assert_eq!(next, batch.indices.len());
+            drawn += next;
+        }
+        assert_eq!(drawn, instances.len() * 6);
+
+        let textures: Vec<Vec<TextureId>> = batches.iter().map(|b| b.draws.iter().map(|d| d.texture_id).collect()).collect();
+        assert_eq!(textures, [vec![instances[0].texture_id], vec![instances[0].texture_id, instances[18000].texture_id]]);
+    }
 
-        ctx.commit_frame();
+    #[test]
+    fn no_decals_no_batches() {
+        assert!(decal_batches(&[]).is_empty());
     }
 }
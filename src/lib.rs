@@ -1,14 +1,41 @@
-use std::{cmp::{max, min}, rc::Rc};
+use std::{cmp::{max, min}, rc::{Rc, Weak}, time::Duration};
 
-pub use layer::{Layer, UniformData};
+pub use layer::{DirtyRect, Layer, LayerFrame, LayerRenderer, LayerResources, SpriteRenderer, UniformData};
+pub use headless::Headless;
 use miniquad::*;
 use glam::*;
+pub use glam;
+pub use miniquad;
 pub use sprite::*;
 pub use decal::*;
+pub use input::*;
+pub use config::EngineConfig;
+pub use error::PgeError;
+pub use stats::FrameStats;
+pub use recording::{RecordingFormat, RecordingOptions};
+pub use resource_pack::{ResourcePack, read_resource};
+pub use sprite_sheet::{SpriteSheet, SheetFrame, FrameId};
+pub use atlas::{Atlas, AtlasBuilder, DecalRegion};
+pub use animation::{Animation, AnimationEvent, AnimationFrame, Animator, PlayMode};
+pub use aseprite::{AsepriteFile, AseCel, AseDirection, AseFrame, AseLayer, AseLayerKind, AseTag};
+pub use image::ImageFormat;
 
 mod layer;
 mod sprite;
 mod decal;
+mod headless;
+mod input;
+mod config;
+mod error;
+mod stats;
+mod recording;
+mod resource_pack;
+mod sprite_sheet;
+mod atlas;
+mod animation;
+mod aseprite;
+mod reader;
+pub mod time;
 
 #[allow(unused_variables)]
 pub trait GameLoop {
@@ -17,6 +44,14 @@ pub trait GameLoop {
     fn init(pge: &mut PGE) -> Self::GameType where Self: Sized;
     fn update(&mut self, pge: &mut PGE, dt: f64) {}
     fn fixed_update(&mut self, pge: &mut PGE, dt: f64) {}
+    /// Called once per frame after `update`, right before the layers are rendered
+    fn draw(&mut self, pge: &mut PGE) {}
+    fn on_text_entry_complete(&mut self, pge: &mut PGE, text: &str) {}
+    /// Called when the window is closed or `PGE::quit` was called, return false to keep running
+    fn on_destroy(&mut self, pge: &mut PGE) -> bool { true }
+    fn on_focus_changed(&mut self, pge: &mut PGE, focused: bool) {}
+    /// Called after the window changed size, in physical pixels. The viewport is already updated.
+    fn on_resize(&mut self, pge: &mut PGE, width: f32, height: f32) {}
 }
 
 #[repr(C)]
@@ -39,6 +74,11 @@ impl Pixel {
         Pixel{r, g, b, a}
     }
 
+    /// Normalized 0-1 rgba, as the shaders expect it
+    pub fn to_vec4(&self) -> Vec4 {
+        vec4(self.r as f32, self.g as f32, self.b as f32, self.a as f32) / 255.0
+    }
+
     // HACK?
     pub fn from_rgba_to_bgra(&mut self) {
         std::mem::swap(&mut self.b, &mut self.r);
@@ -77,10 +117,102 @@ pub const VERY_DARK_MAGENTA: Color      = color(64, 0, 64, 255);
 pub const BLACK: Color                  = color(0, 0, 0, 255);
 pub const BLANK: Color                  = color(0, 0, 0, 0);
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PixelMode {
     Normal, Mask, Alpha, Custom
 }
 
+impl PixelMode {
+    /// The pixel that drawing `src` over `dst` leaves, `None` keeps `dst`. Custom modes are
+    /// handled by `PGE::func_pixel_mode` and always give `None` here.
+    #[inline]
+    pub fn blend(&self, dst: &Pixel, src: &Pixel, blend_factor: f32) -> Option<Pixel> {
+        match self {
+            PixelMode::Normal => Some(*src),
+            PixelMode::Mask => (src.a == 255).then_some(*src),
+            PixelMode::Alpha => {
+                let a = (src.a as f32 / 255.0) * blend_factor;
+                let c = 1.0 - a;
+                // cheat: use fused multiply add
+                let r = a.mul_add(src.r as f32, c * dst.r as f32);
+                let g = a.mul_add(src.g as f32, c * dst.g as f32);
+                let b = a.mul_add(src.b as f32, c * dst.b as f32);
+                let a = a.mul_add(src.a as f32, c * dst.a as f32);
+                Some(Pixel::rgba(r as u8, g as u8, b as u8, a as u8))
+            }
+            PixelMode::Custom => None,
+        }
+    }
+}
+
+/// Where the engine sends its frames, either a real window or a CPU compositor
+pub enum Backend {
+    Gpu(Box<dyn RenderingBackend>),
+    Headless(Headless),
+}
+
+impl Backend {
+    pub fn create_texture(&mut self, width: u32, height: u32) -> TextureId {
+        let ctx = match self {
+            Backend::Gpu(ctx) => ctx,
+            Backend::Headless(headless) => return headless.create_texture(width, height),
+        };
+        ctx.new_texture(
+            TextureAccess::Static, 
+            TextureSource::Empty, 
+            TextureParams { 
+                kind: TextureKind::Texture2D, 
+                format: TextureFormat::RGBA8, 
+                wrap: TextureWrap::Clamp, 
+                min_filter: FilterMode::Linear, 
+                mag_filter: FilterMode::Linear, 
+                mipmap_filter: MipmapFilterMode::Linear, 
+                width, 
+                height, 
+                allocate_mipmaps: false,
+                sample_count: 1,
+            })
+    }
+
+    pub fn update_texture(&mut self, id: TextureId, sprite: &Sprite) {
+        match self {
+            Backend::Gpu(ctx) => ctx.texture_update(id, unsafe {
+                std::slice::from_raw_parts(sprite.pixel_data.as_ptr() as *const u8, sprite.pixel_data.len() * 4)
+            }),
+            Backend::Headless(headless) => headless.update_texture(id, sprite),
+        }
+    }
+
+    pub fn read_texture(&mut self, id: TextureId, sprite: &mut Sprite) {
+        let ctx = match self {
+            Backend::Gpu(ctx) => ctx,
+            Backend::Headless(headless) => return headless.read_texture(id, sprite),
+        };
+        let (tw, th) = ctx.texture_size(id);
+        if tw == sprite.width && th == sprite.height {
+            let bytes: &mut [u8] = unsafe {
+                std::slice::from_raw_parts_mut(sprite.pixel_data.as_ptr() as *mut u8, sprite.pixel_data.len() * 4)
+            };
+            ctx.texture_read_pixels(id, bytes);
+        }
+    }
+
+    pub fn delete_texture(&mut self, id: TextureId) {
+        match self {
+            Backend::Gpu(ctx) => ctx.delete_texture(id),
+            Backend::Headless(headless) => headless.delete_texture(id),
+        }
+    }
+}
+
+/// Where the back buffer ends up inside the window, in physical pixels from the top left.
+/// The rest of the window is left black.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub pos: Vec2,
+    pub size: Vec2,
+}
+
 #[derive(Debug)]
 pub struct Renderable {
     sprite: SpriteRef,
@@ -93,121 +225,120 @@ pub struct PGE {
     pub pixel_mode: PixelMode,
     pub blend_factor: f32,
     pub func_pixel_mode: Option<fn(x: i32, y: i32, p1: &Pixel, p2: &Pixel)>,
+    pub decal_mode: DecalMode,
+    pub decal_structure: DecalStructure,
     pub font: Sprite,
+    font_decal: Decal,
+    // 1x1 white texture for decals drawn without one
+    white_decal: Decal,
 
     /// Engine internal stuff
     pub layers: Vec<Layer>,
     pub current_layer: usize,
-    //keyboard_map: HashMap<> TODO:
     pixel_width: i32,
     pixel_height: i32,
-    ctx: Box<dyn RenderingBackend>,
+    backend: Backend,
+    // shared by every layer's renderer, `None` when running headless
+    resources: Option<LayerResources>,
     inv_screen_size: Vec2,
+    window_size: Vec2,
+    viewport: Viewport,
+    // integer scaling, like olc's pixel cohesion
+    pixel_cohesion: bool,
+    // filter for new layer surfaces
+    sample_filter: FilterMode,
 
     // timing stuff
-    accumulator: f64,
+    // fixed update time not simulated yet
+    accumulator: Duration,
     current_time: f64,
-    pub dt: f64,
+    // length of one fixed update
+    tick_rate: Duration,
     pub time: f64,
     pub frames: usize,
     pub fixed_frames: usize,
 
     // input stuff
     pub mouse_pos: IVec2,
+    mouse: Mouse,
+    focused: bool,
+    keyboard: Keyboard,
+    text_entry: TextEntry,
+
+    // set by `quit`, handled at the end of the frame
+    quit_requested: bool,
+
+    stats: FrameStats,
+    // drawn over every other layer while the stats overlay is on
+    overlay: Option<Layer>,
+    // the box drawn on the overlay last frame, it is all that needs clearing
+    overlay_box: IVec2,
+    recorder: Option<recording::Recorder>,
 }
 
 impl PGE {
+    /// Opens a resizable window and runs the game, panics if the sizes are invalid.
+    /// `start` takes an `EngineConfig` for everything else and returns the error instead.
     pub fn construct<GT>(app_name: &str, width: usize, height: usize, pix_width: usize, pix_height: usize) 
         where GT: GameLoop<GameType = GT> + 'static 
         {
-        let mut conf = conf::Conf::default();
+        let config = EngineConfig::new(app_name, width, height).pixel_size(pix_width, pix_height);
+        if let Err(e) = PGE::start::<GT>(config) {
+            panic!("{}", e);
+        }
+    }
 
-        conf.window_title = app_name.to_owned();
-        conf.window_width = (width * pix_width) as i32;
-        conf.window_height = (height * pix_height) as i32;
-        conf.window_resizable = false;
+    pub fn start<GT>(config: EngineConfig) -> Result<(), PgeError>
+        where GT: GameLoop<GameType = GT> + 'static 
+        {
+        config.validate()?;
 
-        miniquad::start(conf, move || {
-            Box::new(App::<GT> {
-                game: None,
-                pge: PGE::new(width, height, pix_width, pix_height),
-            })
+        miniquad::start(config.window_conf(), move || {
+            Box::new(App::<GT>::new(PGE::new(&config)))
         });
+        Ok(())
     }
 
-    fn new(width: usize, height: usize, pix_width: usize, pix_height: usize) -> Self {
-        let mut ctx = window::new_rendering_backend();
-        
-        let back_buffer = Sprite::new(width as u32, height as u32);
-
-        //#[rustfmt::skip]
-        let vertices: [Vertex; 4] = [
-            vert(vec2(-1.0, -1.0), vec2(0., 1.)),
-            vert(vec2( 1.0, -1.0), vec2(1., 1.)),
-            vert(vec2( 1.0,  1.0), vec2(1., 0.)),
-            vert(vec2(-1.0,  1.0), vec2(0., 0.)),
-        ];
-        let vertex_buffer = ctx.new_buffer(
-            BufferType::VertexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&vertices),
-        );
-
-        let indices: [u16; 6] = [0, 1, 2, 0, 2, 3];
-        let index_buffer = ctx.new_buffer(
-            BufferType::IndexBuffer,
-            BufferUsage::Immutable,
-            BufferSource::slice(&indices),
-        );
-
-        let bb_sprite_ref = SpriteRef::new_from_sprite(back_buffer);
-        let len = bb_sprite_ref.get_data_len();
-
-        let bb_texture = ctx.new_texture_from_rgba8(width as u16, height as u16, unsafe {
-            std::slice::from_raw_parts(bb_sprite_ref.get_data_ptr(), len * 4)
-        });
+    fn new(config: &EngineConfig) -> Self {
+        let ctx = window::new_rendering_backend();
+        PGE::with_backend(Backend::Gpu(ctx), config)
+    }
 
-        ctx.texture_set_filter(bb_texture, FilterMode::Nearest, MipmapFilterMode::None);
+    /// Creates an engine without a window or GPU. Layers are composited on the CPU
+    /// every `render` and the result can be read back with `get_frame`.
+    pub fn new_headless(width: usize, height: usize) -> Self {
+        match PGE::headless(&EngineConfig::new("", width, height)) {
+            Ok(pge) => pge,
+            Err(e) => panic!("{}", e),
+        }
+    }
 
-        let bindings = Bindings {
-            vertex_buffers: vec![vertex_buffer],
-            index_buffer: index_buffer,
-            images: vec![bb_texture],
-        };
+    /// `new_headless` with the window related settings ignored
+    pub fn headless(config: &EngineConfig) -> Result<Self, PgeError> {
+        config.validate()?;
+        let headless = Headless::new(config.width as u32, config.height as u32);
+        Ok(PGE::with_backend(Backend::Headless(headless), config))
+    }
 
-        let shader = ctx
-            .new_shader(
-                ShaderSource::Glsl {
-                    vertex: shader::GL_VERTEX,
-                    fragment: shader::GL_FRAGMENT,
-                },
-                shader::meta(),
-            )
-            .unwrap();
-
-        let params = PipelineParams {
-            color_blend: Some(BlendState::new(
-                Equation::Add,
-                BlendFactor::Value(BlendValue::SourceAlpha),
-                BlendFactor::OneMinusValue(BlendValue::SourceAlpha))
-            ),
-            ..Default::default()
+    fn with_backend(mut backend: Backend, config: &EngineConfig) -> Self {
+        let (width, height) = (config.width, config.height);
+        let (pix_width, pix_height) = (config.pixel_width, config.pixel_height);
+        let font = PGE::construct_font_sheet();
+        // glyphs are white on a transparent background so the tint gives them their colour
+        let mut font_mask = Sprite::new(font.width, font.height);
+        for (mask, p) in font_mask.pixel_data.iter_mut().zip(&font.pixel_data) {
+            if p.r > 0 { *mask = WHITE; }
+        }
+        let font_decal = Decal::new_detached(&mut backend, &font_mask);
+        let mut white = Sprite::new(1, 1);
+        white.clear(WHITE);
+        let white_decal = Decal::new_detached(&mut backend, &white);
+        let resources = match &mut backend {
+            Backend::Gpu(ctx) => Some(LayerResources::new(ctx.as_mut())),
+            Backend::Headless(_) => None,
         };
 
-        let pipeline = ctx.new_pipeline_with_params(
-            &[BufferLayout::default()],
-            &[
-                VertexAttribute::new("in_pos", VertexFormat::Float2),
-                VertexAttribute::new("in_uv", VertexFormat::Float2),
-            ],
-            shader,
-            params
-        );
-
-        let bb_sprite_ref2 = Rc::downgrade(&bb_sprite_ref.0);
-
-        
-        PGE { 
+        let mut pge = PGE { 
             screen_width: width, 
             screen_height: height, 
             pixel_width: pix_width as i32, 
@@ -215,41 +346,57 @@ impl PGE {
             pixel_mode: PixelMode::Normal, 
             blend_factor: 1.0, 
             func_pixel_mode: None, 
-            font: PGE::construct_font_sheet(),
-            // TODO: first layer is created inline as it currently requires pge to create one
-            layers: vec![ 
-                Layer { 
-                    offset: Vec2::ZERO, 
-                    scale: Vec2::ONE, 
-                    show: true, 
-                    update: true, 
-                    pipeline,
-                    bindings,
-                    uniforms: [UniformData { tint: vec4(1.,1.,1.,0.5), offset: vec2(0.1,0.1) }],
-                    surface: Renderable { 
-                        sprite: bb_sprite_ref, 
-                        decal: Decal { 
-                            sprite: bb_sprite_ref2,
-                            texture_id: bb_texture,
-                            uv_scale: Vec2::ONE,
-                            width: width as u32,
-                            height: height as u32,
-                        }},
-                    decal_instances: vec![], 
-                    tint: BLANK, 
-                    id: 0 }
-                ], 
+            decal_mode: DecalMode::Normal,
+            decal_structure: DecalStructure::Fan,
+            font,
+            font_decal,
+            white_decal,
+            layers: vec![],
             current_layer: 0,
-            ctx,
-            accumulator: 0.0,
+            backend,
+            resources,
+            accumulator: Duration::ZERO,
             current_time: date::now(),
-            dt: 1.0 / 60.0,
+            tick_rate: time::f64_to_duration(1.0 / config.fixed_update_rate),
             time: 0.0,
             frames: 0,
             fixed_frames: 0,
             mouse_pos: IVec2::ZERO,
-            inv_screen_size: vec2(1.0 / width as f32, 1.0 / height as f32)
+            mouse: Mouse::default(),
+            focused: true,
+            keyboard: Keyboard::default(),
+            text_entry: TextEntry::default(),
+            quit_requested: false,
+            stats: FrameStats::default(),
+            overlay: None,
+            overlay_box: IVec2::ZERO,
+            recorder: None,
+            inv_screen_size: vec2(1.0 / width as f32, 1.0 / height as f32),
+            window_size: Vec2::ZERO,
+            viewport: Viewport { pos: Vec2::ZERO, size: Vec2::ZERO },
+            pixel_cohesion: config.pixel_cohesion,
+            sample_filter: config.sample_filter,
+        };
+        let window_size = match &pge.backend {
+            Backend::Gpu(_) => window::screen_size().into(),
+            Backend::Headless(_) => vec2((width * pix_width) as f32, (height * pix_height) as f32),
+        };
+        pge.set_window_size(window_size);
+
+        // the first layer is the back buffer and is always visible
+        let mut back_buffer = Layer::new(&mut pge, width as u32, height as u32);
+        back_buffer.show = true;
+        back_buffer.update = true;
+        pge.layers.push(back_buffer);
+
+        for _ in 1..config.layers {
+            let layer = pge.create_layer();
+            pge.layers[layer].show = true;
+            pge.layers[layer].update = true;
         }
+        pge.show_stats_overlay(config.stats_overlay);
+
+        pge
     }
 
     pub fn set_draw_target(&mut self, layer: usize, dirty: bool) {
@@ -260,6 +407,62 @@ impl PGE {
         }
     }
 
+    /// Adds a screen sized layer on top of the others and returns its index.
+    /// Like olc's layers it starts hidden, see `enable_layer`.
+    pub fn create_layer(&mut self) -> usize {
+        let layer = Layer::new(self, self.screen_width as u32, self.screen_height as u32);
+        self.layers.push(layer);
+        self.layers.len() - 1
+    }
+
+    /// Shows or hides a layer. Showing it also turns on its updates, so what is drawn
+    /// into it gets uploaded, `set_draw_target` with `false` turns them back off.
+    pub fn enable_layer(&mut self, layer: usize, show: bool) {
+        if layer < self.layers.len() {
+            self.layers[layer].show = show;
+            if show {
+                self.layers[layer].update = true;
+            }
+        }
+    }
+
+    /// Offset as a fraction of the screen size, (0.5, 0.0) moves the layer half a screen right
+    pub fn set_layer_offset(&mut self, layer: usize, x: f32, y: f32) {
+        if layer < self.layers.len() {
+            self.layers[layer].offset = vec2(x, y);
+        }
+    }
+
+    pub fn set_layer_scale(&mut self, layer: usize, x: f32, y: f32) {
+        if layer < self.layers.len() {
+            self.layers[layer].scale = vec2(x, y);
+        }
+    }
+
+    pub fn set_layer_tint(&mut self, layer: usize, tint: &Color) {
+        if layer < self.layers.len() {
+            self.layers[layer].tint = *tint;
+        }
+    }
+
+    /// How the layer blends with the layers behind it
+    pub fn set_layer_blend(&mut self, layer: usize, mode: DecalMode) {
+        if layer < self.layers.len() {
+            self.layers[layer].blend = mode;
+        }
+    }
+
+    /// Replaces how the layer is drawn, like olc's SetLayerCustomRenderFunction. Takes a
+    /// `LayerRenderer` or a closure `|ctx, layer| ...` called inside the frame's render pass.
+    /// Has no effect when running headless.
+    pub fn set_layer_custom_render_function(&mut self, layer: usize, renderer: impl LayerRenderer + 'static) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            if layer.renderer.is_some() {
+                layer.renderer = Some(Box::new(renderer));
+            }
+        }
+    }
+
     pub fn get_mouse_x(&mut self) -> i32 {
         self.mouse_pos.x
     }
@@ -268,97 +471,296 @@ impl PGE {
         self.mouse_pos.y
     }
 
+    pub fn get_mouse(&self, button: MouseButton) -> HWButton {
+        self.mouse.get(button)
+    }
+
+    /// Wheel movement since the last frame, positive is away from the user
+    pub fn get_mouse_wheel(&self) -> f32 {
+        self.mouse.wheel
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    pub fn get_key(&self, key: KeyCode) -> HWButton {
+        self.keyboard.get(key)
+    }
+
+    /// Starts or stops collecting typed characters, starting from `text`.
+    /// Pressing enter stops it and calls `GameLoop::on_text_entry_complete`.
+    pub fn text_entry_enable(&mut self, enable: bool, text: &str) {
+        self.text_entry.enable(enable, text);
+    }
+
+    pub fn is_text_entry_enabled(&self) -> bool {
+        self.text_entry.enabled
+    }
+
+    pub fn text_entry_get_string(&self) -> &str {
+        &self.text_entry.text
+    }
+
+    /// Cursor position as a byte offset into `text_entry_get_string`
+    pub fn text_entry_get_cursor(&self) -> usize {
+        self.text_entry.cursor
+    }
+
     pub fn create_texture(&mut self, width: u32, height: u32) -> TextureId {
-        let texture = self.ctx.new_texture(
-            TextureAccess::Static, 
-            TextureSource::Empty, 
-            TextureParams { 
-                kind: TextureKind::Texture2D, 
-                format: TextureFormat::RGBA8, 
-                wrap: TextureWrap::Clamp, 
-                min_filter: FilterMode::Linear, 
-                mag_filter: FilterMode::Linear, 
-                mipmap_filter: MipmapFilterMode::Linear, 
-                width: width, 
-                height: height, 
-                allocate_mipmaps: false 
-            });
-        texture
+        self.backend.create_texture(width, height)
     }
 
     pub fn update_texture(&mut self, id: TextureId, sprite: &Sprite) {
-        self.ctx.texture_update(id, unsafe {
-            std::slice::from_raw_parts(sprite.pixel_data.as_ptr() as *const u8, sprite.pixel_data.len() * 4)
-        });
+        self.backend.update_texture(id, sprite);
     }
 
     pub fn read_texture(&mut self, id: TextureId, sprite: &mut Sprite) {
-        let (tw, th) = self.ctx.texture_size(id);
-        if tw == sprite.width && th == sprite.height {
-            let bytes: &mut [u8] = unsafe {
-                std::slice::from_raw_parts_mut(sprite.pixel_data.as_ptr() as *mut u8, sprite.pixel_data.len() * 4)
-            };
-            self.ctx.texture_read_pixels(id, bytes);
-        }
+        self.backend.read_texture(id, sprite);
     }
 
     pub fn delete_texture(&mut self, id: TextureId) {
-        self.ctx.delete_texture(id);
+        self.backend.delete_texture(id);
+    }
+
+    /// The GPU context, for creating resources used by custom layer renderers.
+    /// `None` when running headless.
+    pub fn rendering_backend(&mut self) -> Option<&mut dyn RenderingBackend> {
+        match &mut self.backend {
+            Backend::Gpu(ctx) => Some(ctx.as_mut()),
+            Backend::Headless(_) => None,
+        }
+    }
+
+    /// The last composited frame, only available when running headless
+    pub fn get_frame(&self) -> Option<&Sprite> {
+        match &self.backend {
+            Backend::Gpu(_) => None,
+            Backend::Headless(headless) => Some(headless.frame()),
+        }
+    }
+
+    /// Composites every visible layer and the decals queued so far into one screen sized
+    /// sprite, on the CPU. Call it once the frame is drawn, in `update` or `draw`, decals are
+    /// gone after rendering. Layers drawn by a custom renderer only show their sprite surface.
+    pub fn capture_frame(&mut self) -> Sprite {
+        let mut cpu = Headless::new(self.screen_width as u32, self.screen_height as u32);
+        for layer in self.layers.iter().chain(&self.overlay).filter(|l| l.show) {
+            cpu.insert_texture(layer.surface.decal.texture_id, layer.surface.sprite.get_sprite().clone(), self.sample_filter);
+            for di in &layer.decal_instances {
+                if cpu.has_texture(di.texture_id) {
+                    continue;
+                }
+                // decal textures only live on the backend, read them back once each
+                let texture = match &mut self.backend {
+                    Backend::Gpu(ctx) => {
+                        let (w, h) = ctx.texture_size(di.texture_id);
+                        let mut sprite = Sprite::new(w, h);
+                        self.backend.read_texture(di.texture_id, &mut sprite);
+                        sprite
+                    }
+                    Backend::Headless(headless) => match headless.texture(di.texture_id) {
+                        Some(sprite) => sprite.clone(),
+                        None => continue,
+                    },
+                };
+                // `Backend::create_texture` makes every decal texture linear
+                cpu.insert_texture(di.texture_id, texture, FilterMode::Linear);
+            }
+        }
+        cpu.composite(self.layers.iter().chain(&self.overlay));
+        cpu.into_frame()
+    }
+
+    /// Saves `capture_frame` as an image, the format comes from the file extension
+    pub fn save_screenshot<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<(), PgeError> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)?;
+        self.capture_frame().save(path, format)
+    }
+
+    /// Captures rendered frames into a gif or a directory of pngs until `stop_recording`.
+    /// Encoding happens on a background thread.
+    pub fn start_recording<P: AsRef<std::path::Path>>(&mut self, path: P, options: RecordingOptions) -> Result<(), PgeError> {
+        if self.recorder.is_some() {
+            return Err(PgeError::AlreadyRecording);
+        }
+        self.recorder = Some(recording::Recorder::start(path.as_ref().to_owned(), options)?);
+        Ok(())
+    }
+
+    /// Finishes writing the recording, blocking until the encoder is done
+    pub fn stop_recording(&mut self) -> Result<(), PgeError> {
+        match self.recorder.take() {
+            Some(recorder) => recorder.stop(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Blend mode for every decal drawn after this call
+    pub fn set_decal_mode(&mut self, mode: DecalMode) {
+        self.decal_mode = mode;
+    }
+
+    // converts a position in screen pixels to normalized device coordinates
+    #[inline]
+    fn to_ndc(&self, pos: Vec2) -> Vec2 {
+        vec2(
+            (pos.x * self.inv_screen_size.x) * 2.0 - 1.0,
+            1.0 - (pos.y * self.inv_screen_size.y) * 2.0)
+    }
+
+    /// How the vertices given to `draw_explicit_decal` and `draw_polygon_decal` are joined
+    pub fn set_decal_structure(&mut self, structure: DecalStructure) {
+        self.decal_structure = structure;
+    }
+
+    // queues a quad given in screen pixels, corners run top left, bottom left, bottom right, top right
+    fn push_decal_quad(&mut self, texture_id: TextureId, pos: [Vec2; 4], uv: [Vec2; 4], w: [f32; 4], tint: &Color) {
+        let col = tint.to_vec4();
+        let vertices = (0..4)
+            .map(|i| Vertex { pos: self.to_ndc(pos[i]), uv: uv[i], col, w: w[i] })
+            .collect();
+
+        let di = DecalInstance { 
+            texture_id,
+            vertices,
+            tint: *tint, 
+            mode: self.decal_mode,
+            structure: DecalStructure::Fan
+        };
+
+        self.layers[self.current_layer].decal_instances.push(di);
     }
 
     // draws the given decal into the current layer
     pub fn draw_decal(&mut self, pos: Vec2, decal: &Decal, scale: Vec2, tint: &Color) {
-       let screen_space_pos = vec2(
-           (pos.x * self.inv_screen_size.x) * 2.0 - 1.0, 
-           (pos.x * self.inv_screen_size.x) * 2.0 - 1.0);
-       let screen_space_dim = vec2(
-           screen_space_pos.x + (2.0 * decal.width as f32 * self.inv_screen_size.x) * scale.x,
-           screen_space_pos.y - (2.0 * decal.height as f32 * self.inv_screen_size.y) * scale.y,
-       );
-
-       let di = DecalInstance { 
-           vertices: vec![
-            vert(screen_space_pos, vec2(0., 0.)), 
-            vert(vec2(screen_space_pos.x, screen_space_dim.y), vec2(0., 1.)), 
-            vert(screen_space_dim, vec2(1., 1.)),
-                vert(vec2(screen_space_dim.x, screen_space_pos.y), vec2(1., 0.))], 
-           tint: *tint, 
-           mode: DecalMode::Normal,    // TODO: get this from decal mode
-           structure: DecalStructure::Strip  // TODO: this will likely have to create a new vertex
-                                             //      & index buffer if changed
-       };
-
-       self.layers[self.current_layer].decal_instances.push(di);
+        self.draw_partial_decal(pos, decal, Vec2::ZERO, decal.size(), scale, tint);
+    }
+
+    /// Draws the `source_size` area of the decal starting at `source_pos`, both in decal pixels
+    pub fn draw_partial_decal(&mut self, pos: Vec2, decal: &Decal, source_pos: Vec2, source_size: Vec2, scale: Vec2, tint: &Color) {
+        let size = source_size * scale;
+        let corners = [vec2(0., 0.), vec2(0., 1.), vec2(1., 1.), vec2(1., 0.)];
+        self.push_decal_quad(
+            decal.texture_id,
+            corners.map(|c| pos + c * size),
+            corners.map(|c| decal.pixel_to_uv(source_pos + c * source_size)),
+            [1.0; 4],
+            tint);
+    }
+
+    /// Draws the decal rotated by `angle` radians around `center`, which is in decal pixels
+    pub fn draw_rotated_decal(&mut self, pos: Vec2, decal: &Decal, angle: f32, center: Vec2, scale: Vec2, tint: &Color) {
+        let corners = [vec2(0., 0.), vec2(0., 1.), vec2(1., 1.), vec2(1., 0.)];
+        let rotation = Vec2::from_angle(angle);
+        self.push_decal_quad(
+            decal.texture_id,
+            corners.map(|c| pos + rotation.rotate((c * decal.size() - center) * scale)),
+            corners.map(|c| decal.pixel_to_uv(c * decal.size())),
+            [1.0; 4],
+            tint);
+    }
+
+    /// Stretches the decal over any four corners, given top left, bottom left, bottom right,
+    /// top right. Texture coordinates are perspective corrected so the quad does not shear
+    /// along its diagonal.
+    pub fn draw_warped_decal(&mut self, decal: &Decal, pos: &[Vec2; 4], tint: &Color) {
+        // where the diagonals cross, same as olcPixelGameEngine
+        let rd = (pos[2].x - pos[0].x) * (pos[3].y - pos[1].y) - (pos[3].x - pos[1].x) * (pos[2].y - pos[0].y);
+        if rd == 0.0 {
+            return;
+        }
+        let rn = ((pos[3].x - pos[1].x) * (pos[0].y - pos[1].y) - (pos[3].y - pos[1].y) * (pos[0].x - pos[1].x)) / rd;
+        let sn = ((pos[2].x - pos[0].x) * (pos[0].y - pos[1].y) - (pos[2].y - pos[0].y) * (pos[0].x - pos[1].x)) / rd;
+        let center = if (0.0..=1.0).contains(&rn) && (0.0..=1.0).contains(&sn) {
+            pos[0] + rn * (pos[2] - pos[0])
+        } else {
+            Vec2::ZERO
+        };
+
+        // each corner's q is how far it is from the crossing relative to the opposite corner,
+        // the shader divides by w so it gets 1/q
+        let d = pos.map(|p| (p - center).length());
+        let mut w = [1.0; 4];
+        for i in 0..4 {
+            if d[i] != 0.0 {
+                w[i] = d[(i + 2) & 3] / (d[i] + d[(i + 2) & 3]);
+            }
+        }
+
+        let corners = [vec2(0., 0.), vec2(0., 1.), vec2(1., 1.), vec2(1., 0.)];
+        self.push_decal_quad(decal.texture_id, *pos, corners.map(|c| decal.pixel_to_uv(c * decal.size())), w, tint);
+    }
+
+    /// Draws arbitrary vertices joined according to `decal_structure`. `uv` is relative to the
+    /// decal, from 0 to 1, and `col` tints each vertex. With no decal the shape is flat coloured.
+    pub fn draw_explicit_decal(&mut self, decal: Option<&Decal>, pos: &[Vec2], uv: &[Vec2], col: &[Color]) {
+        let decal = decal.unwrap_or(&self.white_decal);
+        let vertices = pos.iter().zip(uv).zip(col)
+            .map(|((p, uv), c)| vert_col(self.to_ndc(*p), decal.uv_offset + *uv * decal.uv_scale, c.to_vec4()))
+            .collect();
+
+        let di = DecalInstance {
+            texture_id: decal.texture_id,
+            vertices,
+            tint: WHITE,
+            mode: self.decal_mode,
+            structure: self.decal_structure,
+        };
+
+        self.layers[self.current_layer].decal_instances.push(di);
+    }
+
+    /// Like `draw_explicit_decal` with a single tint for every vertex
+    pub fn draw_polygon_decal(&mut self, decal: Option<&Decal>, pos: &[Vec2], uv: &[Vec2], tint: &Color) {
+        let col = vec![*tint; pos.len()];
+        self.draw_explicit_decal(decal, pos, uv, &col);
+    }
+
+    /// Draws text with the built in font as decals, so it can be scaled by fractional amounts
+    pub fn draw_string_decal(&mut self, pos: Vec2, text: &str, col: &Color, scale: Vec2) {
+        let font = Decal { sprite: Weak::new(), ..self.font_decal };
+        let mut spos = Vec2::ZERO;
+
+        for c in text.chars() {
+            if c == '\n' {
+                spos.x = 0.0;
+                spos.y += 8.0 * scale.y;
+            } else {
+                let mut ox: i32 = 15;
+                let mut oy: i32 = 5;
+                if c.is_ascii() && c >= ' ' {
+                    ox = ((c as u32 - 32) % 16) as i32;
+                    oy = ((c as u32 - 32) / 16) as i32;
+                }
+                let source = vec2(ox as f32, oy as f32) * 8.0;
+                self.draw_partial_decal(pos + spos, &font, source, vec2(8.0, 8.0), scale, col);
+                spos.x += 8.0 * scale.x;
+            }
+        }
     }
 
     #[inline]
     pub fn draw(&mut self, x: i32, y: i32, p: &Pixel) {
         if self.current_layer < self.layers.len() {
+            self.layers[self.current_layer].mark_dirty(x, y);
             match self.pixel_mode {
-                PixelMode::Normal => {
-                    self.layers[self.current_layer].surface.sprite.set_pixel(x, y, p);
-                }
-                PixelMode::Mask => {
-                    if p.a == 255 {
-                        self.layers[self.current_layer].surface.sprite.set_pixel(x, y, p);
-                    }
-                },
-                PixelMode::Alpha => {
-                    let d = self.layers[self.current_layer].surface.sprite.get_pixel(x, y);
-                    let a = (p.a as f32 / 255.0) * self.blend_factor;
-                    let c = 1.0 - a;
-                    // cheat: use fused multiply add
-                    let r = a.mul_add(p.r as f32, c * d.r as f32);
-                    let g = a.mul_add(p.g as f32, c * d.g as f32);
-                    let b = a.mul_add(p.b as f32, c * d.b as f32);
-                    let a = a.mul_add(p.a as f32, c * d.a as f32);
-                    self.layers[self.current_layer].surface.sprite.set_pixel(x, y, &Pixel::rgba(r as u8, g as u8, b as u8, a as u8));
-                },
                 PixelMode::Custom => {
                     if let Some(fpm) = self.func_pixel_mode {
                         fpm(x, y, &self.layers[self.current_layer].surface.sprite.get_pixel(x, y), p);
                     }
                 }
+                _ => {
+                    let sprite = &mut self.layers[self.current_layer].surface.sprite;
+                    if let Some(c) = self.pixel_mode.blend(&sprite.get_pixel(x, y), p, self.blend_factor) {
+                        sprite.set_pixel(x, y, &c);
+                    }
+                }
             }
         }
     }
@@ -375,12 +777,12 @@ impl PGE {
 
         loop {
             self.draw(x, y, p);
-            if sx > 0 && sy > 0 { if x >= x2 && y >= y2 { break }};
-            if sx > 0 && sy < 0 { if x >= x2 && y <= y2 { break }};
-            if sx < 0 && sy > 0 { if x <= x2 && y >= y2 { break }};
-            if sx < 0 && sy < 0 { if x <= x2 && y <= y2 { break }};
-            if err > -dx {err = err - dy; x = x + sx; }
-            if err < dy  {err = err + dx; y = y + sy; }        
+            if sx > 0 && sy > 0 && x >= x2 && y >= y2 { break };
+            if sx > 0 && sy < 0 && x >= x2 && y <= y2 { break };
+            if sx < 0 && sy > 0 && x <= x2 && y >= y2 { break };
+            if sx < 0 && sy < 0 && x <= x2 && y <= y2 { break };
+            if err > -dx {err -= dy; x += sx; }
+            if err < dy  {err += dx; y += sy; }        
         }
     }
 
@@ -416,29 +818,21 @@ impl PGE {
 		while y0 >= x0
 		{
             // Modified to draw scan-lines instead of edges
-            if y-y0 > 0 && y-y0 < self.screen_height as i32 {
-                if scanline_rendered[(y - y0) as usize] == false {
-                    self.draw_line(x - x0, y - y0, x + x0, y - y0, p);
-                    scanline_rendered[(y - y0) as usize] = true;
-                }
+            if y-y0 > 0 && y-y0 < self.screen_height as i32 && !scanline_rendered[(y - y0) as usize] {
+                self.draw_line(x - x0, y - y0, x + x0, y - y0, p);
+                scanline_rendered[(y - y0) as usize] = true;
             }
-            if y-x0 > 0 && y-x0 < self.screen_height as i32 {
-                if scanline_rendered[(y - x0) as usize] == false {
-                    self.draw_line(x - y0, y - x0, x + y0, y - x0, p);
-                    scanline_rendered[(y - x0) as usize] = true;
-                }
+            if y-x0 > 0 && y-x0 < self.screen_height as i32 && !scanline_rendered[(y - x0) as usize] {
+                self.draw_line(x - y0, y - x0, x + y0, y - x0, p);
+                scanline_rendered[(y - x0) as usize] = true;
             }
-            if y+y0 > 0 && y+y0 < self.screen_height as i32 {
-                if scanline_rendered[(y + y0) as usize] == false {
-                    self.draw_line(x - x0, y + y0, x + x0, y + y0, p);
-                    scanline_rendered[(y + y0) as usize] = true;
-                }
+            if y+y0 > 0 && y+y0 < self.screen_height as i32 && !scanline_rendered[(y + y0) as usize] {
+                self.draw_line(x - x0, y + y0, x + x0, y + y0, p);
+                scanline_rendered[(y + y0) as usize] = true;
             }
-            if y+x0 > 0 && y+x0 < self.screen_height as i32 {
-                if scanline_rendered[(y + x0) as usize] == false {
-                    self.draw_line(x - y0, y + x0, x + y0, y + x0, p);
-                    scanline_rendered[(y + x0) as usize] = true;
-                }
+            if y+x0 > 0 && y+x0 < self.screen_height as i32 && !scanline_rendered[(y + x0) as usize] {
+                self.draw_line(x - y0, y + x0, x + y0, y + x0, p);
+                scanline_rendered[(y + x0) as usize] = true;
             }
 
 			if d < 0 { x0 += 1; d += 4 * x0 + 6;  }
@@ -476,12 +870,14 @@ impl PGE {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn draw_triangle(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, x3: i32, y3: i32, p: &Pixel) {
         self.draw_line(x1, y1, x2, y2, p);
 		self.draw_line(x2, y2, x3, y3, p);
 		self.draw_line(x3, y3, x1, y1, p);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn fill_triangle(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, x3: i32, y3: i32, col: &Pixel) {
         // we use tuples for this for now
         let v0 = (x1, y1);
@@ -555,46 +951,36 @@ impl PGE {
         }
     }
 
-    pub fn draw_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, scale: usize) {
-        if scale > 1 {
-            for j in 0..sprite.height as i32 {
-                for i in 0..sprite.width as i32 {
-                    for is in 0..scale {
-                        for js in 0..scale {
-                            self.draw(x + (i * scale as i32) + is as i32, y + (j * scale as i32) + js as i32, &sprite.get_pixel(i, j));
-                        }
-                    }
-                }
-            }
-        } else {
-            for j in 0..sprite.height as i32 {
-                for i in 0..sprite.width as i32 {
-                    self.draw(x + i, y + j, &sprite.get_pixel(i, j));
-                }
-            }
-        }
+    pub fn draw_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, scale: usize, flip: Flip) {
+        self.draw_partial_sprite(x, y, sprite, 0, 0, sprite.width as i32, sprite.height as i32, scale, flip);
     }
 
-    pub fn draw_parital_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, ox: i32, oy: i32, w: i32, h: i32, scale: usize) {      
-        if scale > 1 {
-            for j in 0..h as i32 {
-                for i in 0..w as i32 {
+    /// Draws the `w` by `h` area at `ox`, `oy` of the sprite. Pixels outside the sprite follow its sample mode.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_partial_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, ox: i32, oy: i32, w: i32, h: i32, scale: usize, flip: Flip) {
+        // walk the source backwards along flipped axes
+        let (fxs, fxm) = if flip.horizontal() { (w - 1, -1) } else { (0, 1) };
+        let (fys, fym) = if flip.vertical() { (h - 1, -1) } else { (0, 1) };
+        let scale = scale.max(1) as i32;
+
+        for j in 0..h {
+            for i in 0..w {
+                let p = sprite.get_pixel(ox + fxs + i * fxm, oy + fys + j * fym);
+                for js in 0..scale {
                     for is in 0..scale {
-                        for js in 0..scale {
-                            self.draw(x + (i * scale as i32) + is as i32, y + (j * scale as i32) + js as i32, &sprite.get_pixel(ox + i, oy + j));
-                        }
+                        self.draw(x + i * scale + is, y + j * scale + js, &p);
                     }
                 }
             }
-        } else {
-            for j in 0..h as i32 {
-                for i in 0..w as i32 {
-                    self.draw(x + i, y + j, &sprite.get_pixel(ox + i, oy + j));
-                }
-            }
         }
     }
 
+    #[deprecated(note = "renamed to `draw_partial_sprite`, which also takes a `Flip`")]
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw_parital_sprite(&mut self, x: i32, y: i32, sprite: &Sprite, ox: i32, oy: i32, w: i32, h: i32, scale: usize) {
+        self.draw_partial_sprite(x, y, sprite, ox, oy, w, h, scale, Flip::None);
+    }
+
     pub fn draw_string(&mut self, x: i32, y: i32, text: &str, col: &Pixel, scale: i32) {
         let mut sx: i32 = 0;
         let mut sy: i32 = 0;
@@ -614,22 +1000,22 @@ impl PGE {
                     oy = ((c as u32 - 32) / 16) as i32;
                 }
                 if scale > 1 {
-                    for j in 0..8 as i32 {
-                        for i in 0..8 as i32 {
+                    for j in 0..8 {
+                        for i in 0..8 {
                             if self.font.get_pixel(i + ox * 8, j + oy * 8).r > 0 {
-                                for js in 0..scale as i32 {
-                                    for is in 0..scale as i32 {
-                                        self.draw(x + sx + (i*scale) + is, y + sy + (j*scale) + js, &col);
+                                for js in 0..scale {
+                                    for is in 0..scale {
+                                        self.draw(x + sx + (i*scale) + is, y + sy + (j*scale) + js, col);
                                     }
                                 }
                             }
                         }
                     }
                 } else {
-                    for j in 0..8 as i32 {
-                        for i in 0..8 as i32 {
+                    for j in 0..8 {
+                        for i in 0..8 {
                             if self.font.get_pixel(i + ox * 8, j + oy * 8).r > 0 {
-                                self.draw(x + sx + i, y + sy + j, &col);
+                                self.draw(x + sx + i, y + sy + j, col);
                             }
                         }
                     }
@@ -641,18 +1027,181 @@ impl PGE {
 
     pub fn clear(&mut self, p: &Pixel) {
         self.layers[self.current_layer].surface.sprite.clear(*p);
+        self.layers[self.current_layer].mark_dirty_all();
     }
 
     fn construct_font_sheet() -> Sprite {
-        let data = include_bytes!("../font.png");
-        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png).unwrap();
-        let raw_image = image.as_bytes();
-        Sprite::new_with_data(128, 48, raw_image)
+        // the font ships with the crate, it failing to decode is a build problem
+        Sprite::from_bytes(include_bytes!("../font.png")).expect("the embedded font sheet is a valid png")
+    }
+
+    /// Timings and counters of the last frames
+    pub fn get_stats(&self) -> &FrameStats {
+        &self.stats
+    }
+
+    /// Shows `get_stats` in the top left corner, on a layer of its own above all the others
+    pub fn show_stats_overlay(&mut self, show: bool) {
+        if show && self.overlay.is_none() {
+            let mut overlay = Layer::new(self, self.screen_width as u32, self.screen_height as u32);
+            overlay.update = true;
+            self.overlay = Some(overlay);
+        }
+        if let Some(overlay) = &mut self.overlay {
+            overlay.show = show;
+        }
+    }
+
+    // the overlay is not one of `layers`, it is swapped in so the normal drawing routines work
+    fn draw_stats_overlay(&mut self) {
+        let Some(overlay) = self.overlay.take() else { return };
+        if !overlay.show {
+            self.overlay = Some(overlay);
+            return;
+        }
+
+        let current_layer = self.current_layer;
+        let pixel_mode = std::mem::replace(&mut self.pixel_mode, PixelMode::Normal);
+        self.layers.push(overlay);
+        self.current_layer = self.layers.len() - 1;
+
+        let lines = self.stats.lines();
+        let width = lines.iter().map(|l| l.len()).max().unwrap_or(0) as i32 * 8;
+        let size = ivec2(width + 4, lines.len() as i32 * 10 + 2);
+        // a full clear would upload the whole screen every frame, only the box changes
+        let last = std::mem::replace(&mut self.overlay_box, size);
+        let cleared = size.max(last);
+        self.fill_rect(0, 0, cleared.x, cleared.y, &BLANK);
+        self.fill_rect(0, 0, size.x, size.y, &Pixel::rgba(0, 0, 0, 160));
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_string(2, 2 + i as i32 * 10, line, &WHITE, 1);
+        }
+
+        self.overlay = self.layers.pop();
+        self.current_layer = current_layer;
+        self.pixel_mode = pixel_mode;
+    }
+
+    /// Closes the window at the end of the frame, `GameLoop::on_destroy` still gets to veto it
+    pub fn quit(&mut self) {
+        self.quit_requested = true;
+    }
+
+    /// Fixed updates per second
+    pub fn get_tick_rate(&self) -> f64 {
+        time::get_tick_rate(self)
+    }
+
+    /// Changes how often `GameLoop::fixed_update` is called, in updates per second
+    pub fn set_tick_rate(&mut self, tick_rate: f64) -> Result<(), PgeError> {
+        time::set_tick_rate(self, tick_rate)
+    }
+
+    /// The `dt` every `fixed_update` gets, in seconds
+    pub fn get_fixed_dt(&self) -> f64 {
+        time::duration_to_f64(self.tick_rate)
+    }
+
+    /// How far between the last fixed update and the next one this frame is, from 0 to 1.
+    /// Blend the previous and current physics state by this in `update` for smooth motion.
+    pub fn get_alpha(&self) -> f64 {
+        time::duration_to_f64(self.accumulator) / time::duration_to_f64(self.tick_rate)
+    }
+
+    /// Size of the window in physical pixels
+    pub fn get_window_size(&self) -> Vec2 {
+        self.window_size
+    }
+
+    pub fn get_viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    /// With cohesion on (the default) the back buffer only grows in whole multiples of its
+    /// pixel size so every pixel stays the same size, otherwise it fills as much of the
+    /// window as its aspect ratio allows
+    pub fn set_pixel_cohesion(&mut self, cohesion: bool) {
+        self.pixel_cohesion = cohesion;
+        self.set_window_size(self.window_size);
+    }
+
+    // fits the back buffer in the window and centres it
+    fn set_window_size(&mut self, window_size: Vec2) {
+        let pixel = vec2(self.pixel_width as f32, self.pixel_height as f32);
+        // one screen pixel at the smallest scale that keeps its aspect ratio
+        let base = vec2(self.screen_width as f32, self.screen_height as f32) * pixel / pixel.min_element();
+        let fit = (window_size / base).min_element();
+        let scale = if self.pixel_cohesion && fit >= 1.0 { fit.floor() } else { fit };
+
+        let size = base * scale;
+        self.window_size = window_size;
+        self.viewport = Viewport { pos: ((window_size - size) * 0.5).floor(), size };
+    }
+
+    // window coordinates to screen pixels, through the same transform the back buffer is drawn with
+    fn window_to_screen(&self, pos: Vec2) -> IVec2 {
+        let screen = vec2(self.screen_width as f32, self.screen_height as f32);
+        let p = ((pos - self.viewport.pos) / self.viewport.size * screen).floor().as_ivec2();
+        p.clamp(IVec2::ZERO, screen.as_ivec2() - 1)
+    }
+
+    pub fn resize(&mut self, width: f32, height: f32) {
+        self.set_window_size(vec2(width, height));
+        if let Backend::Gpu(ctx) = &mut self.backend {
+            for layer in &mut self.layers {
+                layer.resize(ctx.as_mut(), width, height);
+            }
+        }
     }
 
     pub fn render(&mut self) {
+        self.draw_stats_overlay();
+        if let Some(mut recorder) = self.recorder.take() {
+            if recorder.wants_frame(self.stats.last_frame_time()) {
+                recorder.push(self.capture_frame());
+            }
+            self.recorder = Some(recorder);
+        }
+        self.stats.upload_bytes = 0;
+        self.stats.decals_per_layer.clear();
+        self.stats.decals_per_layer.extend(self.layers.iter().map(|l| l.decal_instances.len()));
+
+        match &mut self.backend {
+            Backend::Gpu(ctx) => {
+                let resources = self.resources.as_ref().unwrap();
+                // uploads and offscreen work have to happen before the frame's pass begins
+                for layer in self.layers.iter_mut().chain(&mut self.overlay).filter(|l| l.show) {
+                    self.stats.upload_bytes += layer.prepare(ctx.as_mut(), resources);
+                }
+
+                // one pass for the whole frame, every layer is drawn over the last
+                ctx.begin_default_pass(PassAction::clear_color(0.0, 0.0, 0.0, 1.0));
+                // GL counts the viewport from the bottom of the window
+                let Viewport { pos, size } = self.viewport;
+                let bottom = self.window_size.y - pos.y - size.y;
+                ctx.apply_viewport(pos.x as i32, bottom as i32, size.x as i32, size.y as i32);
+                for layer in self.layers.iter_mut().chain(&mut self.overlay).filter(|l| l.show) {
+                    layer.render(ctx.as_mut(), resources);
+                }
+                ctx.end_render_pass();
+                ctx.commit_frame();
+            }
+            Backend::Headless(headless) => {
+                // same upload rules as the GPU so a frame looks the same on both
+                for layer in self.layers.iter_mut().chain(&mut self.overlay).filter(|l| l.show) {
+                    if let Some(rect) = layer.take_dirty() {
+                        let texture = layer.surface.decal.texture_id;
+                        headless.update_texture_part(texture, &layer.surface.sprite.get_sprite(), &rect);
+                        self.stats.upload_bytes += rect.bytes();
+                    }
+                }
+                headless.composite(self.layers.iter().chain(&self.overlay));
+            }
+        }
+
+        // decals are queued again every frame
         for layer in &mut self.layers {
-            layer.render(&mut self.ctx);
+            layer.decal_instances.clear();
         }
     }
 }
@@ -660,70 +1209,205 @@ impl PGE {
 pub struct App<T> {
     pub pge: PGE,
     pub game: Option<Box<dyn GameLoop<GameType = T>>>,
+    // a quit went through, only used by `step`
+    quit: bool,
 }
 
 impl<T> EventHandler for App<T> where T: GameLoop<GameType = T> + 'static {
     fn mouse_motion_event(&mut self, x: f32, y: f32) {
-        // Mouse coords come in screen space
-		// But leave in pixel space
-        let x = x as i32;
-        let y = y as i32;
-		self.pge.mouse_pos.x = x / self.pge.pixel_width;
-		self.pge.mouse_pos.y = y / self.pge.pixel_height;
+        // Mouse coords come in window space, the letterbox bars clamp to the nearest edge
+        self.pge.mouse_pos = self.pge.window_to_screen(vec2(x, y));
+    }
 
-		if self.pge.mouse_pos.x >= self.pge.screen_width as i32 {
-			self.pge.mouse_pos.x = self.pge.screen_width as i32 - 1;
+    fn resize_event(&mut self, width: f32, height: f32) {
+        self.pge.resize(width, height);
+        if let Some(game) = &mut self.game {
+            game.on_resize(&mut self.pge, width, height);
         }
-		if self.pge.mouse_pos.y >= self.pge.screen_height as i32 {
-			self.pge.mouse_pos.y = self.pge.screen_height as i32 - 1;
+    }
+
+    fn mouse_wheel_event(&mut self, _x: f32, y: f32) {
+        self.pge.mouse.wheel += y;
+    }
+
+    fn mouse_button_down_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.mouse_motion_event(x, y);
+        self.pge.mouse.button_down(button);
+    }
+
+    fn mouse_button_up_event(&mut self, button: MouseButton, x: f32, y: f32) {
+        self.mouse_motion_event(x, y);
+        self.pge.mouse.button_up(button);
+    }
+
+    // miniquad reports focus changes as minimize/restore on desktop
+    fn window_minimized_event(&mut self) {
+        self.pge.focused = false;
+        self.pge.keyboard.release_all();
+        self.pge.mouse.release_all();
+        if let Some(game) = &mut self.game {
+            game.on_focus_changed(&mut self.pge, false);
+        }
+    }
+
+    fn window_restored_event(&mut self) {
+        self.pge.focused = true;
+        if let Some(game) = &mut self.game {
+            game.on_focus_changed(&mut self.pge, true);
+        }
+    }
+
+    // the close button and `PGE::quit` both end up here
+    fn quit_requested_event(&mut self) {
+        if !self.confirm_quit() {
+            window::cancel_quit();
+        } else {
+            // games that care about a failed recording stop it themselves in on_destroy
+            let _ = self.pge.stop_recording();
         }
+    }
+
+    fn key_down_event(&mut self, keycode: KeyCode, _keymods: KeyMods, _repeat: bool) {
+        self.pge.keyboard.key_down(keycode);
+        self.pge.text_entry.key_down(keycode);
+    }
+
+    fn key_up_event(&mut self, keycode: KeyCode, _keymods: KeyMods) {
+        self.pge.keyboard.key_up(keycode);
+    }
 
-		if self.pge.mouse_pos.x < 0
-			{ self.pge.mouse_pos.x = 0; }
-		if self.pge.mouse_pos.y < 0
-			{ self.pge.mouse_pos.y = 0; }
+    fn char_event(&mut self, character: char, _keymods: KeyMods, _repeat: bool) {
+        self.pge.text_entry.char_typed(character);
     }
 
     fn update(&mut self) {
-        if let Some(game) = &mut self.game {
+        if self.game.is_some() {
             let new_time = date::now();
-            let frame_time = new_time - self.pge.current_time;
-            let dt = self.pge.dt;
+            // the clock is not guaranteed to be monotonic
+            let frame_time = (new_time - self.pge.current_time).max(0.0);
             self.pge.current_time = new_time;
+            self.tick(time::f64_to_duration(frame_time));
+            if self.pge.quit_requested {
+                self.pge.quit_requested = false;
+                window::request_quit();
+            }
+        } else {
+            self.game = Some(Box::new(T::init(&mut self.pge)))
+        }
+    }
+
+    fn draw(&mut self) {
+        self.draw_frame();
+    }
+}
+
+impl<T> App<T> where T: GameLoop<GameType = T> + 'static {
+    pub fn new(pge: PGE) -> Self {
+        App { pge, game: None, quit: false }
+    }
+
+    /// True once a `PGE::quit` made while stepping was accepted by `on_destroy`
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    // asks the game whether it may close
+    fn confirm_quit(&mut self) -> bool {
+        match &mut self.game {
+            Some(game) => game.on_destroy(&mut self.pge),
+            None => true,
+        }
+    }
+
+    /// Runs the game for `frames` frames, each one fixed update long, rendering after
+    /// every one, stopping early once the game quits. Meant to be used with `PGE::new_headless`
+    /// for tests and tools.
+    pub fn step(&mut self, frames: usize) {
+        if self.game.is_none() {
+            self.game = Some(Box::new(T::init(&mut self.pge)))
+        }
+        for _ in 0..frames {
+            if self.quit {
+                break;
+            }
+            self.tick(self.pge.tick_rate);
+            self.draw_frame();
+            if self.pge.quit_requested {
+                self.pge.quit_requested = false;
+                self.quit = self.confirm_quit();
+            }
+        }
+    }
+
+    fn tick(&mut self, frame_time: Duration) {
+        if let Some(game) = &mut self.game {
+            // after a long stall (a breakpoint, dragging the window) only catch up this much,
+            // otherwise the fixed updates can fall further behind every frame
+            let frame_time = frame_time.min(time::f64_to_duration(MAX_FRAME_TIME));
             self.pge.accumulator += frame_time;
 
-            // we always call update at max frame rate
-            game.update(&mut self.pge, frame_time);
-            self.pge.frames += 1;
+            if let Some(text) = self.pge.text_entry.completed.take() {
+                game.on_text_entry_complete(&mut self.pge, &text);
+            }
+
+            self.pge.stats.record_frame(time::duration_to_f64(frame_time));
+            let update_start = date::now();
 
             // fixed update is only called at a fixed rate
-            while self.pge.accumulator >= frame_time {
+            let tick_rate = self.pge.tick_rate;
+            let dt = time::duration_to_f64(tick_rate);
+            while self.pge.accumulator >= tick_rate {
                 game.fixed_update(&mut self.pge, dt);
-                self.pge.accumulator -= dt;
+                self.pge.accumulator -= tick_rate;
                 self.pge.time += dt;
                 self.pge.fixed_frames += 1;
             }
-        } else {
-            self.game = Some(Box::new(T::init(&mut self.pge)))
+
+            // we always call update at max frame rate, after the fixed updates so
+            // `get_alpha` says how far into the next one we are
+            game.update(&mut self.pge, time::duration_to_f64(frame_time));
+            self.pge.frames += 1;
+            self.pge.keyboard.end_frame();
+            self.pge.mouse.end_frame();
+            self.pge.stats.update_time = date::now() - update_start;
         }
     }
 
-    fn draw(&mut self) {
+    // the game's draw phase and the layers, timed together
+    fn draw_frame(&mut self) {
+        let render_start = date::now();
+        if let Some(game) = &mut self.game {
+            game.draw(&mut self.pge);
+        }
         self.pge.render();
+        self.pge.stats.render_time = date::now() - render_start;
     }
 }
 
+/// Longest frame the fixed update loop will catch up on, in seconds
+pub const MAX_FRAME_TIME: f64 = 0.25;
+
 #[inline(always)]
 #[must_use]
 pub const fn vert(pos: Vec2, uv: Vec2) -> Vertex {
-    Vertex { pos, uv }
+    Vertex { pos, uv, col: Vec4::ONE, w: 1.0 }
+}
+
+#[inline(always)]
+#[must_use]
+pub const fn vert_col(pos: Vec2, uv: Vec2, col: Vec4) -> Vertex {
+    Vertex { pos, uv, col, w: 1.0 }
 }
 
+// NOTE: attributes are read back to back, so any padding must come after the last field
 #[repr(C)]
 #[derive(Debug, Clone)]
 pub struct Vertex {
     pub pos: Vec2,
     pub uv: Vec2,
+    pub col: Vec4,
+    /// Clip space w, only differs from 1 on warped decals to get perspective correct uvs
+    pub w: f32,
 }
 
 mod shader {
@@ -732,24 +1416,35 @@ mod shader {
     pub const GL_VERTEX: &str = r#"#version 100
     attribute vec2 in_pos;
     attribute vec2 in_uv;
+    attribute vec4 in_col;
+    attribute float in_w;
 
-    varying lowp vec2 texcoord;
-    uniform lowp vec2 offset;
+    // lowp texture coordinates and positions are off by whole texels in a large atlas or layer
+    varying highp vec2 texcoord;
+    varying lowp vec4 color;
+    uniform highp vec2 offset;
+    uniform highp vec2 scale;
 
     void main() {
-        gl_Position = vec4(in_pos + offset, 0, 1);
+        gl_Position = vec4((in_pos * scale + offset) * in_w, 0, in_w);
         texcoord = in_uv;
+        color = in_col;
     }"#;
 
     pub const GL_FRAGMENT: &str = r#"#version 100
-    varying lowp vec2 texcoord;
+    // highp is optional in fragment shaders on GLES2
+    #ifdef GL_FRAGMENT_PRECISION_HIGH
+    varying highp vec2 texcoord;
+    #else
+    varying mediump vec2 texcoord;
+    #endif
+    varying lowp vec4 color;
 
     uniform sampler2D tex;
     uniform lowp vec4 tint;
 
     void main() {
-        lowp vec4 color = texture2D(tex, texcoord);
-        gl_FragColor = vec4(color.xyz * tint.xyz, color.w);
+        gl_FragColor = texture2D(tex, texcoord) * color * tint;
     }"#;
 
     pub fn meta() -> ShaderMeta {
@@ -765,6 +1460,11 @@ mod shader {
                     name: "offset".to_string(), 
                     uniform_type: UniformType::Float2,
                     array_count: 1 
+                },
+                UniformDesc { 
+                    name: "scale".to_string(), 
+                    uniform_type: UniformType::Float2,
+                    array_count: 1 
                 }
                 ] },
         }
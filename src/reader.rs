@@ -0,0 +1,65 @@
+use crate::*;
+
+// little endian reads over a byte slice that fail instead of running off the end,
+// shared by the file formats
+pub(crate) struct Reader<'a> {
+    bytes: &'a [u8],
+    pub(crate) pos: usize,
+    // what running off the end is reported as
+    error: fn() -> PgeError,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(bytes: &'a [u8], error: fn() -> PgeError) -> Self {
+        Reader { bytes, pos: 0, error }
+    }
+
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], PgeError> {
+        let end = self.pos.checked_add(len).ok_or_else(self.error)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or_else(self.error)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        let rest = &self.bytes[self.pos.min(self.bytes.len())..];
+        self.pos = self.bytes.len();
+        rest
+    }
+
+    pub(crate) fn skip(&mut self, len: usize) -> Result<(), PgeError> {
+        self.take(len).map(|_| ())
+    }
+
+    pub(crate) fn seek(&mut self, pos: usize) -> Result<(), PgeError> {
+        if pos > self.bytes.len() {
+            return Err((self.error)());
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, PgeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, PgeError> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn i16(&mut self) -> Result<i16, PgeError> {
+        Ok(self.u16()? as i16)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, PgeError> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // a u16 length followed by that many bytes of utf-8
+    pub(crate) fn string(&mut self) -> Result<String, PgeError> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.take(len)?).into_owned())
+    }
+}
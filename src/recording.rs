@@ -0,0 +1,177 @@
+use std::{fs::{self, File}, io::BufWriter, path::PathBuf, sync::mpsc::{self, Receiver, SyncSender, TrySendError}, thread::JoinHandle};
+
+use image::{codecs::gif::{GifEncoder, Repeat}, Delay, Frame};
+
+use crate::*;
+
+/*
+    Frames are captured on the main thread with `PGE::capture_frame` and handed to
+    a background thread that does the encoding. At most QUEUED_FRAMES wait for it, when
+    the encoder falls further behind frames are dropped and the next one is shown longer.
+*/
+
+const QUEUED_FRAMES: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordingFormat {
+    /// One looping animated gif
+    Gif,
+    /// The path is a directory that gets a `frame_000000.png` per frame
+    PngSequence,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    pub format: RecordingFormat,
+    /// Only every nth rendered frame is captured, 1 captures all of them
+    pub every_nth: usize,
+    /// Keeps every nth pixel in both directions, 1 keeps the full size
+    pub downscale: u32,
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self {
+        RecordingOptions { format: RecordingFormat::Gif, every_nth: 1, downscale: 1 }
+    }
+}
+
+impl RecordingOptions {
+    pub fn new(format: RecordingFormat) -> Self {
+        RecordingOptions { format, ..Default::default() }
+    }
+
+    pub fn every_nth(mut self, n: usize) -> Self {
+        self.every_nth = n;
+        self
+    }
+
+    pub fn downscale(mut self, factor: u32) -> Self {
+        self.downscale = factor;
+        self
+    }
+}
+
+pub(crate) struct Recorder {
+    options: RecordingOptions,
+    // frames seen since the recording started
+    frames: usize,
+    // game time since the last captured frame, becomes its delay
+    elapsed: f64,
+    sender: Option<SyncSender<(Sprite, f64)>>,
+    thread: Option<JoinHandle<Result<(), PgeError>>>,
+}
+
+impl Recorder {
+    pub(crate) fn start(path: PathBuf, options: RecordingOptions) -> Result<Recorder, PgeError> {
+        if options.every_nth == 0 || options.downscale == 0 {
+            return Err(PgeError::InvalidRecordingOptions);
+        }
+
+        // fail here rather than on the thread when the destination is unusable
+        let output = match options.format {
+            RecordingFormat::Gif => Output::Gif(Box::new(GifEncoder::new(BufWriter::new(File::create(&path)?)))),
+            RecordingFormat::PngSequence => {
+                fs::create_dir_all(&path)?;
+                Output::Png(path)
+            }
+        };
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUED_FRAMES);
+        let thread = std::thread::spawn(move || encode(output, receiver));
+
+        Ok(Recorder { options, frames: 0, elapsed: 0.0, sender: Some(sender), thread: Some(thread) })
+    }
+
+    // whether this frame should be captured, `frame_time` is how long the previous one took
+    #[allow(clippy::manual_is_multiple_of)] // is_multiple_of needs Rust 1.87
+    pub(crate) fn wants_frame(&mut self, frame_time: f64) -> bool {
+        self.elapsed += frame_time;
+        self.frames += 1;
+        (self.frames - 1) % self.options.every_nth == 0
+    }
+
+    pub(crate) fn push(&mut self, frame: Sprite) {
+        let frame = downscale(frame, self.options.downscale);
+        let delay = std::mem::take(&mut self.elapsed);
+        if let Some(sender) = &self.sender {
+            match sender.try_send((frame, delay)) {
+                // a full queue drops the frame, its time goes to the next one
+                Err(TrySendError::Full((_, delay))) => self.elapsed += delay,
+                // the thread only hangs up after an error, which `stop` reports
+                Err(TrySendError::Disconnected(_)) | Ok(()) => {}
+            }
+        }
+    }
+
+    /// Waits for the encoder to write out everything it was sent
+    pub(crate) fn stop(mut self) -> Result<(), PgeError> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> Result<(), PgeError> {
+        self.sender = None;
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or(Err(PgeError::RecordingFailed)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+enum Output {
+    Gif(Box<GifEncoder<BufWriter<File>>>),
+    Png(PathBuf),
+}
+
+// runs on the recording thread until the sender is dropped
+fn encode(mut output: Output, frames: Receiver<(Sprite, f64)>) -> Result<(), PgeError> {
+    if let Output::Gif(encoder) = &mut output {
+        encoder.set_repeat(Repeat::Infinite)?;
+    }
+
+    // a frame is shown until the next one, so its delay only comes with the frame after it
+    let mut pending: Option<Sprite> = None;
+    let mut index = 0;
+    let mut last_delay = 0.0;
+    for (frame, delay) in frames {
+        if let Some(previous) = pending.replace(frame) {
+            write_frame(&mut output, previous, delay, index)?;
+            index += 1;
+        }
+        last_delay = delay;
+    }
+    if let Some(last) = pending {
+        write_frame(&mut output, last, last_delay, index)?;
+    }
+    Ok(())
+}
+
+fn write_frame(output: &mut Output, frame: Sprite, delay: f64, index: usize) -> Result<(), PgeError> {
+    match output {
+        Output::Gif(encoder) => {
+            let delay = Delay::from_saturating_duration(time::f64_to_duration(delay.max(0.0)));
+            encoder.encode_frame(Frame::from_parts(frame.to_image(), 0, 0, delay))?;
+        }
+        Output::Png(dir) => frame.save_png(dir.join(format!("frame_{:06}.png", index)))?,
+    }
+    Ok(())
+}
+
+fn downscale(frame: Sprite, factor: u32) -> Sprite {
+    if factor == 1 {
+        return frame;
+    }
+    let mut small = Sprite::new((frame.width / factor).max(1), (frame.height / factor).max(1));
+    for y in 0..small.height {
+        for x in 0..small.width {
+            let p = frame.get_pixel((x * factor) as i32, (y * factor) as i32);
+            small.set_pixel(x as i32, y as i32, &p);
+        }
+    }
+    small
+}
@@ -0,0 +1,181 @@
+use std::{cell::RefCell, collections::BTreeMap, fs, path::Path, rc::Rc};
+
+use crate::{reader::Reader, *};
+
+/*
+    A single file holding many assets, like olc::ResourcePack.
+
+    ResourcePack::from_directory("assets")?.save("assets.pak", Some("secret"))?;
+    ResourcePack::load("assets.pak", Some("secret"))?.mount();
+    let logo = Sprite::load("logo.png")?; // read from the pack, or from disk when it isn't in there
+
+    Layout: the magic, then everything else xor'ed with the key when there is one
+        b"PGEPACK1"
+        b"PACK" u32 file count
+        per file: u32 name length, name, u32 size, u32 offset from the start of the data
+        the file data
+    Numbers are little endian. The key only hides the contents from casual looks, it is not encryption.
+*/
+
+const MAGIC: &[u8; 8] = b"PGEPACK1";
+// first bytes after the magic, a wrong key turns them into something else
+const CHECK: &[u8; 4] = b"PACK";
+
+thread_local! {
+    static MOUNTED: RefCell<Option<Rc<ResourcePack>>> = const { RefCell::new(None) };
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResourcePack {
+    // names use '/' between directories, relative to the packed directory
+    files: BTreeMap<String, Vec<u8>>,
+}
+
+impl ResourcePack {
+    pub fn new() -> Self {
+        ResourcePack::default()
+    }
+
+    /// Packs every file below `dir`, named by its path relative to `dir`
+    pub fn from_directory<P: AsRef<Path>>(dir: P) -> Result<Self, PgeError> {
+        let mut pack = ResourcePack::new();
+        pack.add_directory(dir.as_ref(), "")?;
+        Ok(pack)
+    }
+
+    fn add_directory(&mut self, dir: &Path, prefix: &str) -> Result<(), PgeError> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.add_directory(&entry.path(), &format!("{}/", name))?;
+            } else {
+                self.add_file(&name, fs::read(entry.path())?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds or replaces a file
+    pub fn add_file(&mut self, name: &str, data: Vec<u8>) {
+        self.files.insert(normalize(name), data);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(&normalize(name)).map(Vec::as_slice)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.files.contains_key(&normalize(name))
+    }
+
+    /// Names of the packed files in sorted order
+    pub fn file_names(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    pub fn to_bytes(&self, key: Option<&str>) -> Result<Vec<u8>, PgeError> {
+        let mut body = CHECK.to_vec();
+        push_u32(&mut body, self.files.len())?;
+        let mut offset = 0;
+        for (name, data) in &self.files {
+            push_u32(&mut body, name.len())?;
+            body.extend_from_slice(name.as_bytes());
+            push_u32(&mut body, data.len())?;
+            push_u32(&mut body, offset)?;
+            offset += data.len();
+        }
+        for data in self.files.values() {
+            body.extend_from_slice(data);
+        }
+        scramble(&mut body, key);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.append(&mut body);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8], key: Option<&str>) -> Result<Self, PgeError> {
+        let body = bytes.strip_prefix(MAGIC.as_slice()).ok_or(PgeError::InvalidResourcePack)?;
+        let mut body = body.to_vec();
+        scramble(&mut body, key);
+
+        let mut reader = Reader::new(&body, || PgeError::InvalidResourcePack);
+        if reader.take(CHECK.len())? != CHECK {
+            return Err(PgeError::InvalidResourcePack);
+        }
+        let count = reader.u32()? as usize;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let len = reader.u32()? as usize;
+            let name = String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| PgeError::InvalidResourcePack)?;
+            entries.push((name, reader.u32()? as usize, reader.u32()? as usize));
+        }
+
+        let data = reader.rest();
+        let mut files = BTreeMap::new();
+        for (name, size, offset) in entries {
+            let file = offset.checked_add(size).and_then(|end| data.get(offset..end));
+            files.insert(name, file.ok_or(PgeError::InvalidResourcePack)?.to_vec());
+        }
+        Ok(ResourcePack { files })
+    }
+
+    /// Writes the pack to one file, `key` obfuscates everything but the magic
+    pub fn save<P: AsRef<Path>>(&self, path: P, key: Option<&str>) -> Result<(), PgeError> {
+        fs::write(path, self.to_bytes(key)?)?;
+        Ok(())
+    }
+
+    /// Reads a pack written by `save`, with the same key
+    pub fn load<P: AsRef<Path>>(path: P, key: Option<&str>) -> Result<Self, PgeError> {
+        ResourcePack::from_bytes(&fs::read(path)?, key)
+    }
+
+    /// Makes `read_resource`, and so `Sprite::load`, look in this pack before the disk.
+    /// Replaces the pack mounted before, on this thread.
+    pub fn mount(self) {
+        MOUNTED.with(|mounted| *mounted.borrow_mut() = Some(Rc::new(self)));
+    }
+
+    pub fn unmount() {
+        MOUNTED.with(|mounted| *mounted.borrow_mut() = None);
+    }
+}
+
+/// Reads a file from the mounted pack, falling back to the disk when it isn't packed
+pub fn read_resource<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, PgeError> {
+    let path = path.as_ref();
+    let packed = MOUNTED.with(|mounted| {
+        let mounted = mounted.borrow();
+        let pack = mounted.as_ref()?;
+        pack.get(&path.to_string_lossy()).map(<[u8]>::to_vec)
+    });
+    match packed {
+        Some(data) => Ok(data),
+        None => Ok(fs::read(path)?),
+    }
+}
+
+// "./sprites\\hero.png" and "sprites/hero.png" name the same file
+fn normalize(name: &str) -> String {
+    let name = name.replace('\\', "/");
+    name.split('/').filter(|part| !part.is_empty() && *part != ".").collect::<Vec<_>>().join("/")
+}
+
+// xor with the key repeated, so doing it twice undoes it
+fn scramble(bytes: &mut [u8], key: Option<&str>) {
+    let key = match key {
+        Some(key) if !key.is_empty() => key.as_bytes(),
+        _ => return,
+    };
+    for (b, k) in bytes.iter_mut().zip(key.iter().cycle()) {
+        *b ^= k;
+    }
+}
+
+fn push_u32(bytes: &mut Vec<u8>, n: usize) -> Result<(), PgeError> {
+    let n = u32::try_from(n).map_err(|_| PgeError::InvalidResourcePack)?;
+    bytes.extend_from_slice(&n.to_le_bytes());
+    Ok(())
+}
@@ -1,19 +1,35 @@
-use std::{rc::Rc, cell::{RefCell, Ref}};
+use std::{rc::Rc, cell::{RefCell, Ref}, path::Path};
+use miniquad::FilterMode;
 use crate::*;
 
 
-#[derive(Debug, Clone)]
+/// How pixels outside the sprite are read
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mode {
+    /// Blank
     Normal,
+    /// Wrapped around, the sprite tiles
     Periodic,
+    /// The nearest edge pixel
     Clamp,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Flip {
     None,
     Horizontal,
     Vertical,
+    Both,
+}
+
+impl Flip {
+    pub fn horizontal(&self) -> bool {
+        matches!(self, Flip::Horizontal | Flip::Both)
+    }
+
+    pub fn vertical(&self) -> bool {
+        matches!(self, Flip::Vertical | Flip::Both)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -25,7 +41,15 @@ impl SpriteRef {
     }
 
     pub fn new_with_data(width: u32, height: u32, data: &[u8]) -> SpriteRef {
-        SpriteRef(Rc::new(RefCell::new(Sprite::new_with_data(width, height, data))))
+        SpriteRef::new_from_sprite(Sprite::new_with_data(width, height, data))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SpriteRef, PgeError> {
+        Ok(SpriteRef::new_from_sprite(Sprite::load(path)?))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<SpriteRef, PgeError> {
+        Ok(SpriteRef::new_from_sprite(Sprite::from_bytes(bytes)?))
     }
 
     // consumes sprite!
@@ -40,12 +64,7 @@ impl SpriteRef {
 
     #[inline]
     pub fn get_pixel(&self, x: i32, y: i32) -> Pixel {
-        let sprite = self.0.borrow();
-        if x >= 0 && x < sprite.width as i32 && y >= 0 && y < sprite.height as i32 {
-            sprite.pixel_data[(y * sprite.width as i32 + x) as usize].clone()
-        } else {
-            Pixel::rgba(0,0,0,0)
-        }
+        self.0.borrow().get_pixel(x, y)
     }
 
     #[inline]
@@ -54,7 +73,7 @@ impl SpriteRef {
         let height = self.0.borrow().height;
         if x >= 0 && x < width as i32 && y >= 0 && y < height as i32 {
             if let Ok(sprite) = &mut self.0.try_borrow_mut() {
-                sprite.pixel_data[(y * width as i32 + x) as usize] = p.clone();
+                sprite.pixel_data[(y * width as i32 + x) as usize] = *p;
             }
         }
     }
@@ -64,6 +83,8 @@ impl SpriteRef {
         sprite.clear(p);
     }
 
+    /// # Safety
+    /// The pointer is only valid while the sprite is alive and its pixels are not resized.
     pub unsafe fn get_data_ptr(&self) -> *const u8 {
         self.0.borrow().pixel_data.as_ptr() as *const u8
     }
@@ -80,6 +101,7 @@ impl SpriteRef {
         self.0.borrow().pixel_data.len()
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn clone(&self) -> Rc<RefCell<Sprite>> {
         self.0.clone()
     }
@@ -103,15 +125,29 @@ impl Sprite {
         }
     }
 
+    /// `data` is rgba8, four bytes per pixel row by row. Missing pixels are left blank.
     pub fn new_with_data(width: u32, height: u32, data: &[u8]) -> Sprite {
-        unsafe {
-            Sprite {
-                width,
-                height,
-                sample_mode: Mode::Normal,
-                pixel_data: std::slice::from_raw_parts(data.as_ptr() as *const Pixel, data.len() / 4).to_vec(),
-            }
+        let mut sprite = Sprite::new(width, height);
+        for (p, rgba) in sprite.pixel_data.iter_mut().zip(data.chunks_exact(4)) {
+            *p = Pixel::rgba(rgba[0], rgba[1], rgba[2], rgba[3]);
         }
+        sprite
+    }
+
+    /// Reads an image file through `read_resource`, the format is detected from its contents
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Sprite, PgeError> {
+        Sprite::from_bytes(&read_resource(path)?)
+    }
+
+    /// Decodes an image held in memory, like one pulled in with `include_bytes!`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Sprite, PgeError> {
+        Ok(Sprite::from_image(&image::load_from_memory(bytes)?))
+    }
+
+    /// Converts any colour type to rgba8
+    pub fn from_image(image: &image::DynamicImage) -> Sprite {
+        let rgba = image.to_rgba8();
+        Sprite::new_with_data(rgba.width(), rgba.height(), rgba.as_raw())
     }
 
     pub fn from_rgba_to_bgra(&mut self) {
@@ -122,27 +158,42 @@ impl Sprite {
         }
     }
 
+    /// Coordinates outside the sprite are handled by `sample_mode`
     #[inline]
     pub fn get_pixel(&self, x: i32, y: i32) -> Pixel {
-        if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
-            self.pixel_data[(y * self.width as i32 + x) as usize].clone()
+        let (w, h) = (self.width as i32, self.height as i32);
+        if w == 0 || h == 0 {
+            return BLANK;
+        }
+        let (x, y) = match self.sample_mode {
+            Mode::Normal => (x, y),
+            Mode::Periodic => (x.rem_euclid(w), y.rem_euclid(h)),
+            Mode::Clamp => (x.clamp(0, w - 1), y.clamp(0, h - 1)),
+        };
+        if x >= 0 && x < w && y >= 0 && y < h {
+            self.pixel_data[(y * w + x) as usize]
         } else {
-            Pixel::rgba(0,0,0,0)
+            BLANK
         }
     }
 
     #[inline]
     pub fn set_pixel(&mut self, x: i32, y: i32, p: &Pixel) {
         if x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32 {
-            self.pixel_data[(y * self.width as i32 + x) as usize] = p.clone();
+            self.pixel_data[(y * self.width as i32 + x) as usize] = *p;
         }
     }
 
     #[inline]
     pub fn sample(&self, x: f32, y: f32) -> Pixel {
-        let sx = (x * self.width as f32) as i32;
-        let sy = (y * self.height as f32) as i32;
-        self.get_pixel(sx, sy)
+        // floor so negative coordinates wrap to the right pixel
+        let sx = (x * self.width as f32).floor() as i32;
+        let sy = (y * self.height as f32).floor() as i32;
+        match self.sample_mode {
+            // 1.0 is still the last pixel, like olc's Sample
+            Mode::Normal => self.get_pixel(sx.min(self.width as i32 - 1), sy.min(self.height as i32 - 1)),
+            _ => self.get_pixel(sx, sy),
+        }
     }
 
     #[inline]
@@ -156,14 +207,135 @@ impl Sprite {
 		let u_opposite = 1.0 - u_ratio;
 		let v_opposite = 1.0 - v_ratio;
 
-		let p1 = self.get_pixel(x.max(0), y.max(0));
-		let p2 = self.get_pixel((x+1).min(self.width as i32 - 1), y.max(0));
-		let p3 = self.get_pixel(x.max(0), (y+1).min(self.height as i32 - 1));
-		let p4 = self.get_pixel((x+1).min(self.width as i32 - 1), (y+1).min(self.height as i32 - 1));
+		// neighbours past the edge wrap when periodic and repeat the edge otherwise
+		let (x0, x1, y0, y1) = match self.sample_mode {
+			Mode::Periodic => (x, x + 1, y, y + 1),
+			_ => (x.max(0), (x+1).min(self.width as i32 - 1), y.max(0), (y+1).min(self.height as i32 - 1)),
+		};
+		let p1 = self.get_pixel(x0, y0);
+		let p2 = self.get_pixel(x1, y0);
+		let p3 = self.get_pixel(x0, y1);
+		let p4 = self.get_pixel(x1, y1);
 
-		Pixel::rgb(((p1.r as f32 * u_opposite + p2.r as f32 * u_ratio) * v_opposite + (p3.r as f32 * u_opposite + p4.r as f32 * u_ratio) * v_ratio) as u8,
+		Pixel::rgba(((p1.r as f32 * u_opposite + p2.r as f32 * u_ratio) * v_opposite + (p3.r as f32 * u_opposite + p4.r as f32 * u_ratio) * v_ratio) as u8,
 			       ((p1.g as f32 * u_opposite + p2.g as f32 * u_ratio) * v_opposite + (p3.g as f32 * u_opposite + p4.g as f32 * u_ratio) * v_ratio) as u8,
-                   ((p1.b as f32 * u_opposite + p2.b as f32 * u_ratio) * v_opposite + (p3.b as f32 * u_opposite + p4.b as f32 * u_ratio) * v_ratio) as u8)
+                   ((p1.b as f32 * u_opposite + p2.b as f32 * u_ratio) * v_opposite + (p3.b as f32 * u_opposite + p4.b as f32 * u_ratio) * v_ratio) as u8,
+                   ((p1.a as f32 * u_opposite + p2.a as f32 * u_ratio) * v_opposite + (p3.a as f32 * u_opposite + p4.a as f32 * u_ratio) * v_ratio) as u8)
+    }
+
+    // a sprite of the given size with each pixel from `f`, keeping this sprite's sample mode
+    fn generate(&self, width: u32, height: u32, f: impl Fn(i32, i32) -> Pixel) -> Sprite {
+        let mut sprite = Sprite::new(width, height);
+        sprite.sample_mode = self.sample_mode;
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                sprite.pixel_data[(y * width as i32 + x) as usize] = f(x, y);
+            }
+        }
+        sprite
+    }
+
+    /// Copies the `width` by `height` area at `x`, `y`. Pixels outside follow the sample mode.
+    pub fn sub_sprite(&self, x: i32, y: i32, width: u32, height: u32) -> Sprite {
+        self.generate(width, height, |i, j| self.get_pixel(x + i, y + j))
+    }
+
+    /// Every pixel becomes a `scale` by `scale` block, like olc's Duplicate
+    pub fn duplicate_scaled(&self, scale: u32) -> Sprite {
+        let scale = scale.max(1);
+        self.generate(self.width * scale, self.height * scale, |x, y| self.get_pixel(x / scale as i32, y / scale as i32))
+    }
+
+    /// Resamples to `width` by `height`, `FilterMode::Linear` blends neighbouring pixels
+    pub fn resize(&self, width: u32, height: u32, filter: FilterMode) -> Sprite {
+        if self.width == 0 || self.height == 0 {
+            return self.generate(width, height, |_, _| BLANK);
+        }
+        // pixel centres map onto pixel centres
+        let uv = |x: i32, y: i32| ((x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32);
+        match filter {
+            FilterMode::Nearest => self.generate(width, height, |x, y| {
+                let (u, v) = uv(x, y);
+                self.get_pixel((u * self.width as f32) as i32, (v * self.height as f32) as i32)
+            }),
+            FilterMode::Linear => self.generate(width, height, |x, y| {
+                let (u, v) = uv(x, y);
+                self.sample_bl(u, v)
+            }),
+        }
+    }
+
+    /// Turned a quarter clockwise
+    pub fn rotate90(&self) -> Sprite {
+        let h = self.height as i32;
+        self.generate(self.height, self.width, |x, y| self.get_pixel(y, h - 1 - x))
+    }
+
+    pub fn rotate180(&self) -> Sprite {
+        self.flipped(Flip::Both)
+    }
+
+    /// Turned a quarter anticlockwise
+    pub fn rotate270(&self) -> Sprite {
+        let w = self.width as i32;
+        self.generate(self.height, self.width, |x, y| self.get_pixel(w - 1 - y, x))
+    }
+
+    pub fn flipped(&self, flip: Flip) -> Sprite {
+        let (w, h) = (self.width as i32, self.height as i32);
+        self.generate(self.width, self.height, |x, y| {
+            let x = if flip.horizontal() { w - 1 - x } else { x };
+            let y = if flip.vertical() { h - 1 - y } else { y };
+            self.get_pixel(x, y)
+        })
+    }
+
+    /// Every channel multiplied by the tint's, the way decals are tinted
+    pub fn tinted(&self, tint: &Pixel) -> Sprite {
+        let mul = |c: u8, t: u8| (c as u16 * t as u16 / 255) as u8;
+        self.generate(self.width, self.height, |x, y| {
+            let p = self.get_pixel(x, y);
+            Pixel::rgba(mul(p.r, tint.r), mul(p.g, tint.g), mul(p.b, tint.b), mul(p.a, tint.a))
+        })
+    }
+
+    /// Draws `src` onto this sprite with its top left at `x`, `y`, blending like `PGE::draw`.
+    /// Custom pixel modes need a PGE, here they copy like `PixelMode::Normal`.
+    pub fn blit(&mut self, src: &Sprite, x: i32, y: i32, mode: PixelMode) {
+        let mode = if mode == PixelMode::Custom { PixelMode::Normal } else { mode };
+        for j in 0..src.height as i32 {
+            for i in 0..src.width as i32 {
+                let (dx, dy) = (x + i, y + j);
+                if dx < 0 || dy < 0 || dx >= self.width as i32 || dy >= self.height as i32 {
+                    continue;
+                }
+                let index = (dy * self.width as i32 + dx) as usize;
+                if let Some(p) = mode.blend(&self.pixel_data[index], &src.pixel_data[(j * src.width as i32 + i) as usize], 1.0) {
+                    self.pixel_data[index] = p;
+                }
+            }
+        }
+    }
+
+    /// Copies the pixels into an `image` buffer
+    pub fn to_image(&self) -> image::RgbaImage {
+        let bytes = self.pixel_data.iter().flat_map(|p| [p.r, p.g, p.b, p.a]).collect();
+        image::RgbaImage::from_raw(self.width, self.height, bytes).unwrap()
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), PgeError> {
+        self.save(path, ImageFormat::Png)
+    }
+
+    /// Writes the sprite to a file, formats without an alpha channel drop it
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), PgeError> {
+        let image = image::DynamicImage::ImageRgba8(self.to_image());
+        let image = match format {
+            ImageFormat::Jpeg | ImageFormat::Bmp | ImageFormat::Pnm => image::DynamicImage::ImageRgb8(image.to_rgb8()),
+            _ => image,
+        };
+        image.save_with_format(path, format)?;
+        Ok(())
     }
 
     pub fn get_data(&self) -> &[Pixel] {
@@ -175,4 +347,112 @@ impl Sprite {
             self.pixel_data[i] = p;
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // every pixel red by its index times 10, row after row
+    fn numbered(width: u32, height: u32) -> Sprite {
+        let data: Vec<u8> = (0..width * height).flat_map(|i| [i as u8 * 10, 0, 0, 255]).collect();
+        Sprite::new_with_data(width, height, &data)
+    }
+
+    fn reds(sprite: &Sprite) -> Vec<u8> {
+        sprite.pixel_data.iter().map(|p| p.r).collect()
+    }
+
+    // 3x2, a b c / d e f
+    #[test]
+    fn rotate_non_square() {
+        let sprite = numbered(3, 2);
+        let turned = sprite.rotate90();
+        assert_eq!((turned.width, turned.height), (2, 3));
+        // d a / e b / f c
+        assert_eq!(reds(&turned), [30, 0, 40, 10, 50, 20]);
+
+        let turned = sprite.rotate270();
+        assert_eq!((turned.width, turned.height), (2, 3));
+        // c f / b e / a d
+        assert_eq!(reds(&turned), [20, 50, 10, 40, 0, 30]);
+
+        assert_eq!(reds(&sprite.rotate90().rotate270()), reds(&sprite));
+        assert_eq!(reds(&sprite.rotate90().rotate90()), reds(&sprite.rotate180()));
+    }
+
+    #[test]
+    fn resize_to_nothing_or_one_pixel() {
+        let sprite = numbered(3, 2);
+        for filter in [FilterMode::Nearest, FilterMode::Linear] {
+            for (width, height) in [(0, 0), (0, 2), (3, 0)] {
+                let empty = sprite.resize(width, height, filter);
+                assert_eq!((empty.width, empty.height), (width, height));
+                assert!(empty.pixel_data.is_empty());
+            }
+        }
+
+        // the centre lands on e, linear sits between b and e
+        assert_eq!(reds(&sprite.resize(1, 1, FilterMode::Nearest)), [40]);
+        assert_eq!(reds(&sprite.resize(1, 1, FilterMode::Linear)), [25]);
+
+        // growing a one pixel sprite repeats it
+        let one = numbered(1, 1);
+        assert_eq!(reds(&one.resize(2, 3, FilterMode::Linear)), [0; 6]);
+        assert!(one.resize(2, 3, FilterMode::Nearest).pixel_data.iter().all(|p| p.a == 255));
+
+        let blank = Sprite::new(0, 0).resize(2, 2, FilterMode::Nearest);
+        assert!(blank.pixel_data.iter().all(|p| p.a == 0));
+    }
+
+    #[test]
+    fn blit_clips_to_the_target() {
+        let src = numbered(3, 2);
+        let mut target = Sprite::new(4, 3);
+        target.clear(WHITE);
+
+        // only e and f are left of a blit up and to the left
+        target.blit(&src, -1, -1, PixelMode::Normal);
+        assert_eq!(reds(&target), [40, 50, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255]);
+
+        // only a fits in the bottom right corner
+        target.blit(&src, 3, 2, PixelMode::Normal);
+        assert_eq!(target.get_pixel(3, 2).r, 0);
+        assert_eq!(target.get_pixel(2, 2).r, 255);
+
+        // entirely outside
+        let before = reds(&target);
+        for (x, y) in [(-3, 0), (0, -2), (4, 0), (0, 3), (i32::MIN / 2, i32::MAX / 2)] {
+            target.blit(&src, x, y, PixelMode::Normal);
+        }
+        assert_eq!(reds(&target), before);
+    }
+
+    // 2x2, a b / c d
+    fn sampled(mode: Mode, uvs: &[(f32, f32)]) -> Vec<u8> {
+        let mut sprite = numbered(2, 2);
+        sprite.sample_mode = mode;
+        uvs.iter().map(|&(u, v)| sprite.sample(u, v).r).collect()
+    }
+
+    #[test]
+    fn sample_periodic_wraps() {
+        let uvs = [(0.25, 0.25), (-0.25, 0.25), (-0.75, -0.25), (1.25, 1.75), (1.0, 0.0), (-2.0, 0.75), (3.75, 0.25)];
+        assert_eq!(sampled(Mode::Periodic, &uvs), [0, 10, 20, 20, 0, 20, 10]);
+    }
+
+    #[test]
+    fn sample_clamp_repeats_the_edge() {
+        let uvs = [(0.75, 0.75), (-0.5, -3.0), (1.5, 0.25), (0.25, 2.0), (1.0, 1.0), (-1.0, 5.0), (7.0, -0.01)];
+        assert_eq!(sampled(Mode::Clamp, &uvs), [30, 0, 10, 20, 30, 20, 10]);
+    }
+
+    #[test]
+    fn sample_normal_is_blank_outside() {
+        let mut sprite = numbered(2, 2);
+        sprite.sample_mode = Mode::Normal;
+        // 1.0 is still the last pixel
+        assert_eq!(sprite.sample(1.0, 1.0).r, 30);
+        assert_eq!(sprite.sample(-0.25, 0.5).a, 0);
+        assert_eq!(sprite.sample(0.5, -0.01).a, 0);
+    }
+}
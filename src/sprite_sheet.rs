@@ -0,0 +1,194 @@
+use std::{collections::HashMap, path::Path};
+
+use glam::*;
+use serde_json::Value;
+
+use crate::*;
+
+/*
+    Frames of a sprite sheet, addressed by index or by name.
+
+    let mut sheet = SpriteSheet::grid(Sprite::load("hero.png")?, 16, 16);
+    sheet.create_decal(pge);
+    sheet.draw_frame(pge, 3, ivec2(10, 10), 1, Flip::None);
+    sheet.draw_frame_decal(pge, "idle_0", vec2(10.5, 20.0), Vec2::ONE, Flip::Horizontal, &WHITE);
+
+    The JSON descriptors are the ones TexturePacker and Aseprite export, "frames" either as
+    an object keyed by name or as an array of objects with a "filename".
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SheetFrame {
+    pub name: String,
+    /// Area of the sheet in pixels
+    pub pos: IVec2,
+    pub size: IVec2,
+    /// How long the frame is shown in seconds, when the descriptor says
+    pub duration: Option<f64>,
+}
+
+/// A frame by its index or its name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameId<'a> {
+    Index(usize),
+    Name(&'a str),
+}
+
+impl From<usize> for FrameId<'_> {
+    fn from(index: usize) -> Self {
+        FrameId::Index(index)
+    }
+}
+
+impl<'a> From<&'a str> for FrameId<'a> {
+    fn from(name: &'a str) -> Self {
+        FrameId::Name(name)
+    }
+}
+
+#[derive(Debug)]
+pub struct SpriteSheet {
+    pub sprite: SpriteRef,
+    /// Set by `create_decal`, needed for `draw_frame_decal`
+    pub decal: Option<Decal>,
+    pub(crate) frames: Vec<SheetFrame>,
+    names: HashMap<String, usize>,
+}
+
+impl SpriteSheet {
+    /// A sheet with no frames yet, see `add_frame`
+    pub fn new(sprite: Sprite) -> Self {
+        SpriteSheet {
+            sprite: SpriteRef::new_from_sprite(sprite),
+            decal: None,
+            frames: Vec::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    /// Cuts the sprite into `frame_width` by `frame_height` cells, numbered row by row.
+    /// Cells are named by their index, leftover pixels at the edges are not part of any frame.
+    pub fn grid(sprite: Sprite, frame_width: u32, frame_height: u32) -> Self {
+        let (columns, rows) = (sprite.width / frame_width.max(1), sprite.height / frame_height.max(1));
+        let mut sheet = SpriteSheet::new(sprite);
+        let size = ivec2(frame_width as i32, frame_height as i32);
+        for i in 0..columns * rows {
+            let pos = ivec2((i % columns) as i32, (i / columns) as i32) * size;
+            sheet.add_frame(&i.to_string(), pos, size);
+        }
+        sheet
+    }
+
+    /// Parses a JSON descriptor for `sprite`, frames are numbered in the order they are listed
+    pub fn from_json(sprite: Sprite, json: &str) -> Result<Self, PgeError> {
+        let json: Value = serde_json::from_str(json).map_err(|e| PgeError::InvalidSpriteSheet(e.to_string()))?;
+        let mut sheet = SpriteSheet::new(sprite);
+        match &json["frames"] {
+            Value::Object(frames) => {
+                for (name, frame) in frames {
+                    sheet.add_json_frame(name, frame)?;
+                }
+            }
+            Value::Array(frames) => {
+                for frame in frames {
+                    let name = frame["filename"].as_str().ok_or_else(|| invalid("frame without a filename"))?;
+                    sheet.add_json_frame(name, frame)?;
+                }
+            }
+            _ => return Err(invalid("no frames")),
+        }
+        Ok(sheet)
+    }
+
+    /// Reads a JSON descriptor and the image its `meta.image` names, relative to the descriptor.
+    /// Both go through `read_resource`.
+    pub fn load<P: AsRef<Path>>(json_path: P) -> Result<Self, PgeError> {
+        let json_path = json_path.as_ref();
+        let json = String::from_utf8(read_resource(json_path)?).map_err(|e| PgeError::InvalidSpriteSheet(e.to_string()))?;
+        let meta: Value = serde_json::from_str(&json).map_err(|e| PgeError::InvalidSpriteSheet(e.to_string()))?;
+        let image = meta["meta"]["image"].as_str().ok_or_else(|| invalid("no meta.image"))?;
+        let sprite = Sprite::load(json_path.parent().unwrap_or(Path::new("")).join(image))?;
+        SpriteSheet::from_json(sprite, &json)
+    }
+
+    fn add_json_frame(&mut self, name: &str, frame: &Value) -> Result<(), PgeError> {
+        let rect = &frame["frame"];
+        let field = |key: &str| rect[key].as_i64().map(|n| n as i32).ok_or_else(|| invalid(&format!("frame {} has no {}", name, key)));
+        let index = self.add_frame(name, ivec2(field("x")?, field("y")?), ivec2(field("w")?, field("h")?));
+        // aseprite writes milliseconds
+        self.frames[index].duration = frame["duration"].as_f64().map(|ms| ms / 1000.0);
+        Ok(())
+    }
+
+    /// Adds a frame and returns its index. A frame with the same name is replaced.
+    pub fn add_frame(&mut self, name: &str, pos: IVec2, size: IVec2) -> usize {
+        let frame = SheetFrame { name: name.to_owned(), pos, size, duration: None };
+        if let Some(&index) = self.names.get(name) {
+            self.frames[index] = frame;
+            return index;
+        }
+        self.names.insert(name.to_owned(), self.frames.len());
+        self.frames.push(frame);
+        self.frames.len() - 1
+    }
+
+    pub fn frame<'a>(&self, id: impl Into<FrameId<'a>>) -> Option<&SheetFrame> {
+        self.frame_index(id).map(|index| &self.frames[index])
+    }
+
+    pub fn frame_index<'a>(&self, id: impl Into<FrameId<'a>>) -> Option<usize> {
+        match id.into() {
+            FrameId::Index(index) if index < self.frames.len() => Some(index),
+            FrameId::Index(_) => None,
+            FrameId::Name(name) => self.names.get(name).copied(),
+        }
+    }
+
+    /// All frames in index order
+    pub fn frames(&self) -> &[SheetFrame] {
+        &self.frames
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Uploads the sheet so it can be drawn with `draw_frame_decal`
+    pub fn create_decal(&mut self, pge: &mut PGE) {
+        self.decal = Some(Decal::new_from_sprite_ref(pge, &self.sprite));
+    }
+
+    /// Draws a frame with `draw_partial_sprite`, unknown frames draw nothing
+    pub fn draw_frame<'a>(&self, pge: &mut PGE, id: impl Into<FrameId<'a>>, pos: IVec2, scale: usize, flip: Flip) {
+        if let Some(frame) = self.frame(id) {
+            let sprite = self.sprite.get_sprite();
+            pge.draw_partial_sprite(pos.x, pos.y, &sprite, frame.pos.x, frame.pos.y, frame.size.x, frame.size.y, scale, flip);
+        }
+    }
+
+    /// Draws a frame with `draw_partial_decal`. Does nothing before `create_decal` or for unknown frames.
+    pub fn draw_frame_decal<'a>(&self, pge: &mut PGE, id: impl Into<FrameId<'a>>, mut pos: Vec2, mut scale: Vec2, flip: Flip, tint: &Color) {
+        let (Some(decal), Some(frame)) = (&self.decal, self.frame(id)) else {
+            return;
+        };
+        let size = frame.size.as_vec2();
+        // a negative scale mirrors the quad, so start it from the far edge
+        if flip.horizontal() {
+            pos.x += size.x * scale.x;
+            scale.x = -scale.x;
+        }
+        if flip.vertical() {
+            pos.y += size.y * scale.y;
+            scale.y = -scale.y;
+        }
+        pge.draw_partial_decal(pos, decal, frame.pos.as_vec2(), size, scale, tint);
+    }
+}
+
+fn invalid(reason: &str) -> PgeError {
+    PgeError::InvalidSpriteSheet(reason.to_owned())
+}
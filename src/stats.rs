@@ -0,0 +1,65 @@
+use std::collections::VecDeque;
+
+/*
+    Timings are in seconds and measured with miniquad's clock. Frame times are kept
+    for the last FRAME_HISTORY frames, everything else only describes the last frame.
+*/
+
+const FRAME_HISTORY: usize = 120;
+
+#[derive(Debug, Default, Clone)]
+pub struct FrameStats {
+    /// Frames per second averaged over the frame time history
+    pub fps: f64,
+    pub frame_time_min: f64,
+    pub frame_time_max: f64,
+    pub frame_time_avg: f64,
+    /// Time spent in `fixed_update` and `update`
+    pub update_time: f64,
+    /// Time spent in `draw` and rendering the layers
+    pub render_time: f64,
+    /// Layer surface bytes sent to the GPU, or to the headless textures
+    pub upload_bytes: usize,
+    /// Decals queued on each layer, in layer order
+    pub decals_per_layer: Vec<usize>,
+    frame_times: VecDeque<f64>,
+}
+
+impl FrameStats {
+    pub(crate) fn record_frame(&mut self, frame_time: f64) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        let total: f64 = self.frame_times.iter().sum();
+        self.frame_time_avg = total / self.frame_times.len() as f64;
+        self.frame_time_min = self.frame_times.iter().copied().fold(f64::INFINITY, f64::min);
+        self.frame_time_max = self.frame_times.iter().copied().fold(0.0, f64::max);
+        self.fps = if total > 0.0 { self.frame_times.len() as f64 / total } else { 0.0 };
+    }
+
+    pub fn last_frame_time(&self) -> f64 {
+        self.frame_times.back().copied().unwrap_or(0.0)
+    }
+
+    /// Frame times of the last frames, oldest first
+    pub fn frame_times(&self) -> impl Iterator<Item = f64> + '_ {
+        self.frame_times.iter().copied()
+    }
+
+    // what the overlay shows, one entry per line
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let ms = |t: f64| t * 1000.0;
+        let decals: Vec<String> = self.decals_per_layer.iter().map(|n| n.to_string()).collect();
+        vec![
+            format!("fps {:.1}", self.fps),
+            format!("frame {:.2}ms", ms(self.frame_time_avg)),
+            format!("min {:.2} max {:.2}", ms(self.frame_time_min), ms(self.frame_time_max)),
+            format!("update {:.2}ms", ms(self.update_time)),
+            format!("render {:.2}ms", ms(self.render_time)),
+            format!("upload {}kb", self.upload_bytes / 1024),
+            format!("decals {}", decals.join(" ")),
+        ]
+    }
+}
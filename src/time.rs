@@ -2,7 +2,7 @@
 
 use std::time::Duration;
 
-//use crate::Context;
+use crate::{PGE, PgeError};
 
 /// Converts a `std::time::Duration` to an `f64`. This is less accurate, but
 /// usually more useful.
@@ -19,13 +19,98 @@ pub fn f64_to_duration(duration: f64) -> Duration {
     let nanos = (duration.fract() * 1e9) as u32;
     Duration::new(seconds, nanos)
 }
-/*
-/// Gets the update tick rate of the application, in ticks per second.
-pub fn get_tick_rate(ctx: &Context) -> f64 {
-    1.0 / duration_to_f64(ctx.tick_rate)
+
+/// Gets the fixed update tick rate of the engine, in ticks per second.
+pub fn get_tick_rate(pge: &PGE) -> f64 {
+    1.0 / duration_to_f64(pge.tick_rate)
+}
+
+/// Sets the fixed update tick rate of the engine, in ticks per second.
+pub fn set_tick_rate(pge: &mut PGE, tick_rate: f64) -> Result<(), PgeError> {
+    pge.tick_rate = tick_duration(tick_rate)?;
+    Ok(())
+}
+
+/// Converts a tick rate to the length of one tick. Rates so high that a tick
+/// rounds down to zero nanoseconds are rejected, the update loop would never
+/// catch up with them.
+pub(crate) fn tick_duration(tick_rate: f64) -> Result<Duration, PgeError> {
+    if !tick_rate.is_finite() || tick_rate <= 0.0 {
+        return Err(PgeError::InvalidTickRate(tick_rate));
+    }
+    match f64_to_duration(1.0 / tick_rate) {
+        Duration::ZERO => Err(PgeError::InvalidTickRate(tick_rate)),
+        tick => Ok(tick),
+    }
 }
 
-/// Sets the update tick rate of the application, in ticks per second.
-pub fn set_tick_rate(ctx: &mut Context, tick_rate: f64) {
-    ctx.tick_rate = f64_to_duration(1.0 / tick_rate);
-}*/
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::f64_to_duration;
+    use crate::*;
+
+    struct Idle;
+
+    impl GameLoop for Idle {
+        type GameType = Idle;
+
+        fn init(_pge: &mut PGE) -> Self {
+            Idle
+        }
+    }
+
+    // ten fixed updates a second
+    fn app() -> App<Idle> {
+        let mut app = App::<Idle>::new(PGE::new_headless(8, 8));
+        app.game = Some(Box::new(Idle));
+        app.pge.set_tick_rate(10.0).unwrap();
+        app
+    }
+
+    #[test]
+    fn fixed_updates_catch_up_with_frames() {
+        let mut app = app();
+        let mut ticks = Vec::new();
+        for _ in 0..10 {
+            app.tick(Duration::from_millis(30));
+            ticks.push(app.pge.fixed_frames);
+            let alpha = app.pge.get_alpha();
+            assert!((0.0..1.0).contains(&alpha), "{}", alpha);
+        }
+        assert_eq!(ticks, [0, 0, 0, 1, 1, 1, 2, 2, 2, 3]);
+        assert!(app.pge.get_alpha().abs() < 1e-9);
+        assert_eq!(app.pge.frames, 10);
+    }
+
+    #[test]
+    fn long_frames_are_clamped() {
+        let mut app = app();
+        app.tick(Duration::from_secs(5));
+        // MAX_FRAME_TIME is two and a half ticks
+        assert_eq!(app.pge.fixed_frames, 2);
+        assert!((app.pge.get_alpha() - 0.5).abs() < 1e-6, "{}", app.pge.get_alpha());
+
+        app.tick(Duration::from_millis(50));
+        assert_eq!(app.pge.fixed_frames, 3);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut app = app();
+        let mut rng = StdRng::seed_from_u64(13);
+        let mut total = Duration::ZERO;
+        for _ in 0..1000 {
+            let frame = Duration::from_micros(rng.gen_range(0..400_000));
+            total += frame.min(f64_to_duration(MAX_FRAME_TIME));
+            app.tick(frame);
+            let alpha = app.pge.get_alpha();
+            assert!((0.0..1.0).contains(&alpha), "{}", alpha);
+        }
+        let tick = app.pge.tick_rate;
+        assert_eq!(app.pge.fixed_frames as u128, total.as_nanos() / tick.as_nanos());
+    }
+}
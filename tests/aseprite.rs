@@ -0,0 +1,165 @@
+// The fixtures are tiny hand built files:
+//   indexed.aseprite  4x3, 8 bit indexed, two frames, a hidden group and a pingpong tag "idle"
+//   rgba.aseprite     2x2, 32 bit rgba, a compressed cel and the tags "spin" (reverse) and "rest"
+
+use pge::*;
+
+const INDEXED: &[u8] = include_bytes!("fixtures/indexed.aseprite");
+const RGBA: &[u8] = include_bytes!("fixtures/rgba.aseprite");
+
+fn rgba(p: Pixel) -> [u8; 4] {
+    [p.r, p.g, p.b, p.a]
+}
+
+fn at(sprite: &Sprite, x: i32, y: i32) -> [u8; 4] {
+    rgba(sprite.get_pixel(x, y))
+}
+
+#[test]
+fn indexed_file() {
+    let ase = AsepriteFile::from_bytes(INDEXED).unwrap();
+    assert_eq!((ase.width, ase.height), (4, 3));
+    assert_eq!(ase.palette.len(), 4);
+
+    let names: Vec<&str> = ase.layers.iter().map(|l| l.name.as_str()).collect();
+    assert_eq!(names, ["bottom", "top", "grp", "hidden_child"]);
+    assert_eq!(ase.layers[2].kind, AseLayerKind::Group);
+    assert!(!ase.layers[2].visible);
+    assert_eq!(ase.layers[3].child_level, 1);
+
+    let durations: Vec<f64> = ase.frames.iter().map(|f| f.duration).collect();
+    assert_eq!(durations, [0.1, 0.25]);
+    assert_eq!(ase.tags.len(), 1);
+    assert_eq!((ase.tags[0].name.as_str(), ase.tags[0].from, ase.tags[0].to), ("idle", 0, 1));
+}
+
+#[test]
+fn indexed_frames() {
+    let ase = AsepriteFile::from_bytes(INDEXED).unwrap();
+
+    let frame = ase.frame_image(0);
+    assert_eq!((frame.width, frame.height), (4, 3));
+    // the layer in the hidden group is left out
+    assert_eq!(at(&frame, 0, 0), rgba(RED));
+    assert_eq!(at(&frame, 1, 1), rgba(GREEN));
+    // the transparent index shows the layer below
+    assert_eq!(at(&frame, 2, 1), rgba(RED));
+
+    // the bottom cel is linked to frame 0, the top one is half transparent blue at half opacity
+    let frame = ase.frame_image(1);
+    assert_eq!(at(&frame, 3, 2), rgba(RED));
+    let blended = at(&frame, 0, 0);
+    assert!(blended[0] > 180 && blended[0] < 200 && blended[2] > 55 && blended[2] < 75, "{:?}", blended);
+    assert_eq!(blended[3], 255);
+
+    // a single layer is drawn even when hidden
+    let hidden = ase.layer_image(0, 3);
+    assert_eq!(at(&hidden, 0, 0), [0, 0, 255, 128]);
+    assert_eq!(at(&hidden, 1, 0), [0, 0, 0, 0]);
+}
+
+#[test]
+fn indexed_sheet_and_animations() {
+    let ase = AsepriteFile::from_bytes(INDEXED).unwrap();
+    let sheet = ase.sprite_sheet().unwrap();
+    assert_eq!(sheet.len(), 2);
+    assert_eq!(sheet.frame(1).unwrap().pos, glam::ivec2(4, 0));
+    assert_eq!(sheet.frame(1).unwrap().duration, Some(0.25));
+    assert_eq!(at(&sheet.sprite.get_sprite(), 5, 1), at(&ase.frame_image(1), 1, 1));
+
+    let animations = ase.animations(&sheet, 0.1).unwrap();
+    let idle = &animations["idle"];
+    assert_eq!(idle.mode, PlayMode::PingPong);
+    let frames: Vec<(usize, f64)> = idle.frames.iter().map(|f| (f.frame, f.duration)).collect();
+    assert_eq!(frames, [(0, 0.1), (1, 0.25)]);
+}
+
+#[test]
+fn rgba_file() {
+    let ase = AsepriteFile::from_bytes(RGBA).unwrap();
+    assert!(ase.palette.is_empty());
+
+    let frame = ase.frame_image(0);
+    assert_eq!(at(&frame, 0, 0), rgba(RED));
+    assert_eq!(at(&frame, 1, 0), [0, 0, 0, 0]);
+    assert_eq!(at(&frame, 0, 1), rgba(GREEN));
+    assert_eq!(at(&frame, 1, 1), [0, 0, 255, 128]);
+
+    // the cel of frame 1 only covers the right column
+    let frame = ase.frame_image(1);
+    assert_eq!(at(&frame, 0, 0), [0, 0, 0, 0]);
+    assert_eq!(at(&frame, 1, 0), rgba(WHITE));
+    assert_eq!(at(&frame, 1, 1), rgba(BLACK));
+
+    let tags: Vec<(&str, AseDirection)> = ase.tags.iter().map(|t| (t.name.as_str(), t.direction)).collect();
+    assert_eq!(tags, [("spin", AseDirection::Reverse), ("rest", AseDirection::Forward)]);
+}
+
+#[test]
+fn rgba_animations() {
+    let ase = AsepriteFile::from_bytes(RGBA).unwrap();
+    let sheet = ase.sprite_sheet().unwrap();
+    // frame 1 has no duration in the file
+    assert_eq!(sheet.frame(1).unwrap().duration, None);
+
+    let animations = ase.animations(&sheet, 0.5).unwrap();
+    let spin = &animations["spin"];
+    assert_eq!(spin.mode, PlayMode::Loop);
+    let frames: Vec<(usize, f64)> = spin.frames.iter().map(|f| (f.frame, f.duration)).collect();
+    assert_eq!(frames, [(1, 0.5), (0, 0.05)]);
+    assert_eq!(animations["rest"].frames.len(), 1);
+}
+
+#[test]
+fn json_tag_directions() {
+    let sheet = AsepriteFile::from_bytes(INDEXED).unwrap().sprite_sheet().unwrap();
+    let json = r#"{ "meta": { "frameTags": [
+        { "name": "a", "from": 0, "to": 1, "direction": "pingpong_reverse" },
+        { "name": "b", "from": 0, "to": 1 }
+    ] } }"#;
+    let animations = Animation::from_aseprite_json(&sheet, json, 0.1).unwrap();
+    assert_eq!(animations["a"].mode, PlayMode::PingPong);
+    assert_eq!(animations["a"].frames[0].frame, 1);
+    assert_eq!(animations["b"].mode, PlayMode::Loop);
+    assert_eq!(animations["b"].frames[0].frame, 0);
+}
+
+#[test]
+fn not_an_aseprite_file() {
+    let result = AsepriteFile::from_bytes(include_bytes!("../logo_long.png"));
+    assert!(matches!(result, Err(PgeError::InvalidAseprite(_))));
+}
+
+#[test]
+fn truncated_files_fail() {
+    for file in [INDEXED, RGBA] {
+        for len in 0..file.len() {
+            assert!(AsepriteFile::from_bytes(&file[..len]).is_err(), "{} bytes", len);
+        }
+    }
+}
+
+#[test]
+fn huge_canvas_fails_before_allocating() {
+    let mut file = INDEXED.to_vec();
+    // width and height in the header
+    file[8..12].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    assert!(matches!(AsepriteFile::from_bytes(&file), Err(PgeError::InvalidAseprite(_))));
+}
+
+#[test]
+fn huge_cel_fails_before_allocating() {
+    let mut file = RGBA.to_vec();
+    // the compressed cel of frame 0 claims to be 65535x65535, its stream is far shorter
+    let cel = 128 + file[128..].windows(4).position(|w| w == [2, 0, 2, 0]).unwrap();
+    file[cel..cel + 4].copy_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    assert!(AsepriteFile::from_bytes(&file).is_err());
+}
+
+#[test]
+fn too_many_frames_for_a_sheet() {
+    let mut ase = AsepriteFile::from_bytes(RGBA).unwrap();
+    ase.width = 65535;
+    ase.height = 65535;
+    assert!(matches!(ase.sprite_sheet(), Err(PgeError::InvalidAseprite(_))));
+}
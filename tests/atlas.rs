@@ -0,0 +1,106 @@
+use glam::*;
+use pge::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+struct Scene {
+    draw: Box<dyn FnMut(&mut PGE)>,
+}
+
+impl GameLoop for Scene {
+    type GameType = Scene;
+
+    fn init(_pge: &mut PGE) -> Self {
+        Scene { draw: Box::new(|_| {}) }
+    }
+
+    fn draw(&mut self, pge: &mut PGE) {
+        (self.draw)(pge);
+    }
+}
+
+fn rgba(p: Pixel) -> [u8; 4] {
+    [p.r, p.g, p.b, p.a]
+}
+
+// every pixel of every sprite is different, a neighbour bleeding in shows up
+fn sprites(count: usize) -> Vec<Sprite> {
+    let mut rng = StdRng::seed_from_u64(7);
+    (0..count).map(|i| {
+        let mut sprite = Sprite::new(rng.gen_range(1..=20), rng.gen_range(1..=20));
+        for y in 0..sprite.height as i32 {
+            for x in 0..sprite.width as i32 {
+                sprite.set_pixel(x, y, &color(i as u8 * 5, x as u8 * 12, y as u8 * 12, 255));
+            }
+        }
+        sprite
+    }).collect()
+}
+
+// the pixel rect of a region on its page
+fn rect(atlas: &Atlas, region: &DecalRegion) -> (Vec2, Vec2) {
+    let page = atlas.pages[region.page].size();
+    (region.uv_offset * page, region.uv_scale * page)
+}
+
+#[test]
+fn regions_fit_their_pages_without_overlapping() {
+    let mut pge = PGE::new_headless(16, 16);
+    let sprites = sprites(50);
+    let mut builder = AtlasBuilder::new(64, 64).padding(1).extrude(1);
+    for (i, sprite) in sprites.iter().enumerate() {
+        builder.add(&i.to_string(), sprite.clone());
+    }
+    let atlas = builder.build(&mut pge).unwrap();
+    assert!(atlas.pages.len() > 1);
+
+    let rects: Vec<(Vec2, Vec2)> = atlas.regions().iter().map(|r| rect(&atlas, r)).collect();
+    for (i, (region, &(pos, size))) in atlas.regions().iter().zip(&rects).enumerate() {
+        // the uvs land on whole pixels and cover the sprite exactly
+        assert!(pos.distance(pos.round()) < 1e-3 && size.distance(size.round()) < 1e-3, "{} at {} size {}", i, pos, size);
+        assert_eq!(size.round(), vec2(sprites[i].width as f32, sprites[i].height as f32));
+        assert_eq!((region.width, region.height), (sprites[i].width, sprites[i].height));
+
+        // the extrusion stays on the page too
+        let page = atlas.pages[region.page].size();
+        assert!(pos.x >= 1.0 && pos.y >= 1.0 && pos.x + size.x + 1.0 <= page.x + 1e-3 && pos.y + size.y + 1.0 <= page.y + 1e-3, "{} outside its page", i);
+        assert!(page.x <= 64.0 && page.y <= 64.0);
+
+        for (j, (other, &(other_pos, other_size))) in atlas.regions().iter().zip(&rects).enumerate().skip(i + 1) {
+            if other.page != region.page {
+                continue;
+            }
+            // extruded cells plus the padding between them
+            let apart = pos.x + size.x + 2.0 <= other_pos.x - 1.0 + 1e-3
+                || other_pos.x + other_size.x + 2.0 <= pos.x - 1.0 + 1e-3
+                || pos.y + size.y + 2.0 <= other_pos.y - 1.0 + 1e-3
+                || other_pos.y + other_size.y + 2.0 <= pos.y - 1.0 + 1e-3;
+            assert!(apart, "{} and {} overlap", i, j);
+        }
+    }
+}
+
+#[test]
+fn regions_draw_their_sprite() {
+    for i in 0..12 {
+        let expected = sprites(12).swap_remove(i);
+        let mut app = App::<Scene>::new(PGE::new_headless(20, 20));
+        app.game = Some(Box::new(Scene { draw: Box::new(move |pge: &mut PGE| {
+            let mut builder = AtlasBuilder::new(64, 64).extrude(1);
+            for sprite in sprites(12) {
+                builder.add("", sprite);
+            }
+            let atlas = builder.build(pge).unwrap();
+            pge.clear(&BLACK);
+            pge.draw_decal(Vec2::ZERO, atlas.region(i).unwrap(), Vec2::ONE, &WHITE);
+        }) }));
+        app.step(1);
+
+        let frame = app.pge.get_frame().unwrap();
+        for y in 0..expected.height as i32 {
+            for x in 0..expected.width as i32 {
+                let (a, b) = (rgba(frame.get_pixel(x, y)), rgba(expected.get_pixel(x, y)));
+                assert!(a.iter().zip(b).all(|(&a, b)| a.abs_diff(b) <= 2), "region {} at {}, {}: {:?} {:?}", i, x, y, a, b);
+            }
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use pge::*;
+
+#[test]
+fn screen_sizes() {
+    assert!(EngineConfig::new("", 320, 240).validate().is_ok());
+    assert!(EngineConfig::new("", 16384, 16).validate().is_ok());
+    for (width, height) in [(0, 240), (320, 0), (16385, 16), (16384, 16384), (65535, 65535)] {
+        let result = EngineConfig::new("", width, height).validate();
+        assert!(matches!(result, Err(PgeError::InvalidScreenSize { .. })), "{}x{}", width, height);
+    }
+}
+
+#[test]
+fn oversized_screen_fails_before_allocating() {
+    assert!(matches!(PGE::headless(&EngineConfig::new("", 65535, 65535)), Err(PgeError::InvalidScreenSize { .. })));
+}
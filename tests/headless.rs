@@ -0,0 +1,290 @@
+// Golden tests for the drawing functions, rendered with the headless backend.
+// Run with PGE_UPDATE_GOLDEN=1 to rewrite the images in tests/golden after an intended change.
+
+use std::path::PathBuf;
+
+use pge::glam::*;
+use pge::*;
+
+const WIDTH: usize = 16;
+const HEIGHT: usize = 12;
+
+struct Scene {
+    draw: Box<dyn FnMut(&mut PGE)>,
+}
+
+impl GameLoop for Scene {
+    type GameType = Scene;
+
+    fn init(_pge: &mut PGE) -> Self {
+        Scene { draw: Box::new(|_| {}) }
+    }
+
+    fn draw(&mut self, pge: &mut PGE) {
+        (self.draw)(pge);
+    }
+}
+
+// steps one frame of `draw` and returns what ended up on screen
+fn render(draw: impl FnMut(&mut PGE) + 'static) -> Sprite {
+    let mut app = App::<Scene>::new(PGE::new_headless(WIDTH, HEIGHT));
+    app.game = Some(Box::new(Scene { draw: Box::new(draw) }));
+    app.step(1);
+    app.pge.get_frame().expect("headless has a frame").clone()
+}
+
+fn rgba(p: Pixel) -> [u8; 4] {
+    [p.r, p.g, p.b, p.a]
+}
+
+fn at(frame: &Sprite, x: i32, y: i32) -> [u8; 4] {
+    rgba(frame.get_pixel(x, y))
+}
+
+// decals are filtered linearly, texel centres come out within rounding of the texel
+fn near(a: [u8; 4], b: Pixel) -> bool {
+    a.iter().zip(rgba(b)).all(|(&a, b)| a.abs_diff(b) <= 2)
+}
+
+fn golden(name: &str, frame: &Sprite) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(format!("{}.png", name));
+    if std::env::var_os("PGE_UPDATE_GOLDEN").is_some() {
+        frame.save_png(&path).unwrap();
+        return;
+    }
+
+    let expected = Sprite::load(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    assert_eq!((frame.width, frame.height), (expected.width, expected.height), "{} size", name);
+    for y in 0..frame.height as i32 {
+        for x in 0..frame.width as i32 {
+            assert_eq!(at(frame, x, y), at(&expected, x, y), "{} differs at {}, {}", name, x, y);
+        }
+    }
+}
+
+#[test]
+fn draw() {
+    let frame = render(|pge| {
+        pge.clear(&DARK_BLUE);
+        pge.draw(0, 0, &RED);
+        pge.draw(15, 11, &GREEN);
+        // off screen, ignored
+        pge.draw(-1, 3, &RED);
+        pge.draw(16, 3, &RED);
+
+        pge.pixel_mode = PixelMode::Mask;
+        pge.draw(3, 3, &color(255, 255, 255, 0));
+        pge.draw(4, 3, &WHITE);
+
+        pge.pixel_mode = PixelMode::Alpha;
+        pge.draw(5, 3, &color(255, 0, 0, 128));
+        pge.pixel_mode = PixelMode::Normal;
+    });
+
+    assert_eq!(at(&frame, 0, 0), rgba(RED));
+    assert_eq!(at(&frame, 15, 11), rgba(GREEN));
+    assert_eq!(at(&frame, 0, 3), rgba(DARK_BLUE));
+    assert_eq!(at(&frame, 3, 3), rgba(DARK_BLUE));
+    assert_eq!(at(&frame, 4, 3), rgba(WHITE));
+    golden("draw", &frame);
+}
+
+#[test]
+fn fill_rect() {
+    let frame = render(|pge| {
+        pge.clear(&BLACK);
+        pge.fill_rect(2, 2, 4, 3, &YELLOW);
+        // clipped by the screen edge
+        pge.fill_rect(12, 9, 10, 10, &CYAN);
+        pge.draw_rect(7, 1, 4, 4, &MAGENTA);
+    });
+
+    assert_eq!(at(&frame, 2, 2), rgba(YELLOW));
+    assert_eq!(at(&frame, 5, 4), rgba(YELLOW));
+    assert_eq!(at(&frame, 6, 4), rgba(BLACK));
+    assert_eq!(at(&frame, 2, 5), rgba(BLACK));
+    assert_eq!(at(&frame, 15, 11), rgba(CYAN));
+    golden("fill_rect", &frame);
+}
+
+fn checker() -> Sprite {
+    let mut sprite = Sprite::new(2, 2);
+    sprite.set_pixel(0, 0, &RED);
+    sprite.set_pixel(1, 0, &GREEN);
+    sprite.set_pixel(0, 1, &BLUE);
+    sprite.set_pixel(1, 1, &WHITE);
+    sprite
+}
+
+#[test]
+fn draw_sprite() {
+    let frame = render(|pge| {
+        let sprite = checker();
+        pge.clear(&BLACK);
+        pge.draw_sprite(0, 0, &sprite, 1, Flip::None);
+        pge.draw_sprite(3, 0, &sprite, 2, Flip::None);
+        pge.draw_sprite(8, 0, &sprite, 2, Flip::Horizontal);
+        pge.draw_sprite(0, 5, &sprite, 3, Flip::Both);
+        pge.draw_partial_sprite(8, 5, &sprite, 1, 0, 1, 2, 2, Flip::Vertical);
+    });
+
+    assert_eq!(at(&frame, 1, 0), rgba(GREEN));
+    assert_eq!(at(&frame, 4, 1), rgba(RED));
+    assert_eq!(at(&frame, 5, 3), rgba(WHITE));
+    assert_eq!(at(&frame, 8, 0), rgba(GREEN));
+    assert_eq!(at(&frame, 0, 5), rgba(WHITE));
+    assert_eq!(at(&frame, 8, 5), rgba(WHITE));
+    assert_eq!(at(&frame, 8, 7), rgba(GREEN));
+    golden("draw_sprite", &frame);
+}
+
+#[test]
+fn decals() {
+    let frame = render(|pge| {
+        pge.clear(&BLACK);
+        let (decal, _sprite) = Decal::new_from_sprite(pge, checker());
+        pge.draw_decal(vec2(0.0, 0.0), &decal, Vec2::ONE, &WHITE);
+        pge.draw_decal(vec2(3.0, 0.0), &decal, vec2(2.0, 2.0), &WHITE);
+        pge.draw_partial_decal(vec2(8.0, 0.0), &decal, vec2(0.0, 1.0), vec2(2.0, 1.0), vec2(2.0, 2.0), &WHITE);
+        // the tint multiplies the texture
+        pge.draw_decal(vec2(0.0, 5.0), &decal, vec2(2.0, 2.0), &color(255, 255, 255, 128));
+        pge.draw_rotated_decal(vec2(8.0, 8.0), &decal, std::f32::consts::PI, vec2(1.0, 1.0), vec2(2.0, 2.0), &WHITE);
+        pge.draw_warped_decal(&decal, &[vec2(12.0, 4.0), vec2(12.0, 8.0), vec2(16.0, 8.0), vec2(16.0, 4.0)], &WHITE);
+    });
+
+    assert!(near(at(&frame, 0, 0), RED));
+    assert!(near(at(&frame, 1, 1), WHITE));
+    assert!(near(at(&frame, 3, 0), RED));
+    assert!(near(at(&frame, 6, 3), WHITE));
+    assert!(near(at(&frame, 8, 1), BLUE));
+    assert!(near(at(&frame, 11, 1), WHITE));
+    // rotated half a turn about its centre
+    assert!(near(at(&frame, 6, 6), WHITE));
+    assert!(near(at(&frame, 9, 9), RED));
+    golden("decals", &frame);
+}
+
+#[test]
+fn linear_decal_filter() {
+    // decal textures are filtered linearly, a stretched decal blends between texel centres
+    let frame = render(|pge| {
+        pge.clear(&BLACK);
+        let mut sprite = Sprite::new(2, 1);
+        sprite.set_pixel(0, 0, &BLACK);
+        sprite.set_pixel(1, 0, &WHITE);
+        let (decal, _sprite) = Decal::new_from_sprite(pge, sprite);
+        pge.draw_decal(vec2(0.0, 0.0), &decal, vec2(8.0, 1.0), &WHITE);
+    });
+
+    let row: Vec<u8> = (0..16).map(|x| at(&frame, x, 0)[0]).collect();
+    assert_eq!(row[0], 0);
+    assert_eq!(row[15], 255);
+    assert!(row.windows(2).all(|w| w[0] <= w[1]), "{:?}", row);
+    assert!(row[6] > 0 && row[6] < 255, "{:?}", row);
+    golden("linear_decal_filter", &frame);
+}
+
+#[test]
+fn stats_overlay_uploads_only_its_box() {
+    let mut app = App::<Scene>::new(PGE::new_headless(320, 240));
+    app.game = Some(Box::new(Scene { draw: Box::new(|_| {}) }));
+    app.pge.show_stats_overlay(true);
+    app.step(3);
+
+    // the screen sized layer 0 is not drawn to, so only the overlay box is uploaded
+    let uploaded = app.pge.get_stats().upload_bytes;
+    assert!(uploaded > 0 && uploaded < 320 * 240 * 4 / 2, "{} bytes", uploaded);
+    let frame = app.pge.get_frame().unwrap();
+    assert_eq!(at(frame, 319, 239), rgba(BLACK));
+    // the box is black over black, the text in it is not
+    assert!((0..40).any(|x| at(frame, x, 3) != rgba(BLACK)));
+}
+
+// every other column blue, with a half transparent red pixel in the first row
+fn stripes(pge: &mut PGE) {
+    pge.clear(&BLACK);
+    for x in (0..WIDTH as i32).step_by(2) {
+        pge.fill_rect(x, 0, 1, HEIGHT as i32, &BLUE);
+    }
+    pge.pixel_mode = PixelMode::Alpha;
+    pge.draw(1, 0, &color(255, 0, 0, 128));
+    pge.pixel_mode = PixelMode::Normal;
+}
+
+fn assert_capture_matches(mut app: App<Scene>) {
+    app.step(1);
+    let captured = app.pge.capture_frame();
+    let frame = app.pge.get_frame().unwrap();
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            assert_eq!(at(&captured, x, y), at(frame, x, y), "differs at {}, {}", x, y);
+            assert_eq!(at(frame, x, y)[3], 255, "transparent at {}, {}", x, y);
+        }
+    }
+}
+
+#[test]
+fn capture_matches_scaled_layer() {
+    let mut app = App::<Scene>::new(PGE::new_headless(WIDTH, HEIGHT));
+    app.game = Some(Box::new(Scene { draw: Box::new(stripes) }));
+    app.pge.set_layer_scale(0, 2.0, 1.0);
+    assert_capture_matches(app);
+}
+
+#[test]
+fn capture_matches_offset_layer() {
+    let mut app = App::<Scene>::new(PGE::new_headless(WIDTH, HEIGHT));
+    app.game = Some(Box::new(Scene { draw: Box::new(stripes) }));
+    // half a pixel
+    app.pge.set_layer_offset(0, 0.5 / WIDTH as f32, 0.0);
+    assert_capture_matches(app);
+}
+
+#[test]
+fn screenshot_is_opaque() {
+    let mut app = App::<Scene>::new(PGE::new_headless(WIDTH, HEIGHT));
+    app.game = Some(Box::new(Scene { draw: Box::new(|pge: &mut PGE| {
+        pge.clear(&BLUE);
+        pge.pixel_mode = PixelMode::Alpha;
+        pge.draw(0, 0, &color(255, 0, 0, 128));
+        pge.pixel_mode = PixelMode::Normal;
+    }) }));
+    app.step(1);
+
+    let path = std::env::temp_dir().join(format!("pge-screenshot-{}.png", std::process::id()));
+    app.pge.save_screenshot(&path).unwrap();
+    let saved = Sprite::load(&path);
+    let _ = std::fs::remove_file(&path);
+
+    let shown = at(app.pge.get_frame().unwrap(), 0, 0);
+    assert_eq!(shown[3], 255);
+    assert_eq!(at(&app.pge.capture_frame(), 0, 0), shown);
+    assert_eq!(at(&saved.unwrap(), 0, 0), shown);
+}
+
+#[test]
+fn recorded_frame_matches_screen() {
+    let dir = std::env::temp_dir().join(format!("pge-recording-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let mut app = App::<Scene>::new(PGE::new_headless(WIDTH, HEIGHT));
+    app.game = Some(Box::new(Scene { draw: Box::new(|pge: &mut PGE| {
+        stripes(pge);
+        let (decal, _sprite) = Decal::new_from_sprite(pge, checker());
+        pge.draw_decal(vec2(4.0, 4.0), &decal, vec2(2.0, 2.0), &color(255, 255, 255, 128));
+    }) }));
+    app.pge.set_layer_scale(0, 2.0, 1.0);
+    app.pge.start_recording(&dir, RecordingOptions::new(RecordingFormat::PngSequence)).unwrap();
+    app.step(1);
+    app.pge.stop_recording().unwrap();
+
+    let recorded = Sprite::load(dir.join("frame_000000.png"));
+    let _ = std::fs::remove_dir_all(&dir);
+    let recorded = recorded.unwrap();
+    let frame = app.pge.get_frame().unwrap();
+    for y in 0..HEIGHT as i32 {
+        for x in 0..WIDTH as i32 {
+            assert_eq!(at(&recorded, x, y), at(frame, x, y), "differs at {}, {}", x, y);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fs, path::PathBuf};
+
+use pge::*;
+
+// a fresh directory per test, removed again when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("pge-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// packs a small asset directory into <temp>/assets.pak
+fn packed(temp: &TempDir, key: Option<&str>) -> PathBuf {
+    let assets = temp.0.join("assets");
+    fs::create_dir_all(assets.join("sprites")).unwrap();
+    fs::write(assets.join("sprites/packed_logo.png"), include_bytes!("../logo_long.png")).unwrap();
+    fs::write(assets.join("readme.txt"), b"hello").unwrap();
+
+    let pack = ResourcePack::from_directory(&assets).unwrap();
+    let path = temp.0.join("assets.pak");
+    pack.save(&path, key).unwrap();
+    path
+}
+
+#[test]
+fn round_trip_through_a_file() {
+    let temp = TempDir::new("round-trip");
+    let path = packed(&temp, Some("secret"));
+
+    let pack = ResourcePack::load(&path, Some("secret")).unwrap();
+    assert_eq!(pack.file_names().collect::<Vec<_>>(), ["readme.txt", "sprites/packed_logo.png"]);
+    assert_eq!(pack.get("./readme.txt"), Some(&b"hello"[..]));
+
+    // the name only exists in the pack, not relative to the working directory
+    assert!(Sprite::load("sprites/packed_logo.png").is_err());
+    let expected = Sprite::from_bytes(include_bytes!("../logo_long.png")).unwrap();
+    pack.mount();
+    let sprite = Sprite::load("sprites/packed_logo.png");
+    ResourcePack::unmount();
+
+    let sprite = sprite.unwrap();
+    assert_eq!((sprite.width, sprite.height), (expected.width, expected.height));
+    assert!(sprite.pixel_data.iter().zip(&expected.pixel_data).all(|(a, b)| (a.r, a.g, a.b, a.a) == (b.r, b.g, b.b, b.a)));
+}
+
+#[test]
+fn wrong_key() {
+    let temp = TempDir::new("wrong-key");
+    let path = packed(&temp, Some("secret"));
+
+    assert!(matches!(ResourcePack::load(&path, Some("guess")), Err(PgeError::InvalidResourcePack)));
+    assert!(matches!(ResourcePack::load(&path, None), Err(PgeError::InvalidResourcePack)));
+}
+
+#[test]
+fn truncated_file() {
+    let temp = TempDir::new("truncated");
+    let path = packed(&temp, None);
+
+    let bytes = fs::read(&path).unwrap();
+    for len in [0, 4, 12, 20, bytes.len() - 1] {
+        fs::write(&path, &bytes[..len]).unwrap();
+        assert!(matches!(ResourcePack::load(&path, None), Err(PgeError::InvalidResourcePack)), "{} bytes", len);
+    }
+}